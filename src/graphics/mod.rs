@@ -1,6 +1,6 @@
-use notan::{app::{Color, Graphics, Texture}, draw::{CreateDraw, DrawImages, DrawShapes}, math::{Mat3, Vec2}};
+use notan::{app::{Color, Graphics, Texture}, draw::{CreateDraw, Draw, DrawImages, DrawShapes}, math::{Mat3, Vec2}};
 
-use crate::world::{chunk, ChunkCoord, Coordinate, Surface};
+use crate::world::{area::{BoundingBox, ChunkArea}, chart::Chart, chunk, tile::TileManager, ChunkCoord, Coordinate, Surface};
 
 #[derive(Debug, Clone, Default)]
 pub struct GraphicsData {    
@@ -11,6 +11,10 @@ pub struct GraphicsData {
     pub prev_cam_pos: Coordinate,
     pub zoom: f32,
     refreshpos: Coordinate,
+    ///chunks covered by tilebuffer
+    buffer_chunks: ChunkArea,
+    ///chunks in buffer_chunks that changed since they were drawn into tilebuffer
+    dirty_chunks: Vec<ChunkCoord>,
 }
 
 impl GraphicsData {
@@ -22,7 +26,22 @@ impl GraphicsData {
             window_size: (0.0, 0.0),
             prev_cam_pos: Coordinate::new(0.0, 0.0),
             zoom: 1.0,
-            refreshpos: Coordinate::new(0.0, 0.0)
+            refreshpos: Coordinate::new(0.0, 0.0),
+            buffer_chunks: ChunkArea::empty(),
+            dirty_chunks: vec![],
+        }
+    }
+}
+
+///marks the given chunks for redrawing if they are drawn in the tile buffer. only their part
+///of the buffer is drawn again, the rest is kept
+pub fn invalidate_chunks(graphicsdata: &mut GraphicsData, chunks: &[ChunkCoord]) {
+    if graphicsdata.tilebuffer.is_none() {
+        return;
+    }
+    for chunk in chunks {
+        if graphicsdata.buffer_chunks.contains(*chunk) && !graphicsdata.dirty_chunks.contains(chunk) {
+            graphicsdata.dirty_chunks.push(*chunk);
         }
     }
 }
@@ -73,34 +92,15 @@ impl ScreenWorldConverter {
     }
 }
 
-trait SignedFloor {
-    ///rounds the number "in" towards 0 regardless of sign
-    fn signed_floor(self) -> Self;
-}
-impl SignedFloor for f32 {
-    ///rounds the number "in" towards 0 regardless of sign
-    fn signed_floor(self) -> Self {
-        if self < 0.0 { return self.ceil(); }
-        else { return self.floor(); }
-    }
-}
-
-trait SignedCeil {
-    ///rounds the number "out" away from 0 regardless of sign
-    fn signed_ceil(self) -> Self;
-}
-impl SignedCeil for f32 {
-    ///rounds the number "out" away from 0 regardless of sign
-    fn signed_ceil(self) -> Self {
-        if self < 0.0 { return self.floor(); }
-        else { return self.ceil(); }
-    }
+///the part of the world that fits in the window around center
+fn view_bounds(center: Coordinate, graphicsdata: &GraphicsData, scale: f32) -> BoundingBox {
+    BoundingBox::around(center, graphicsdata.window_size.0 * 0.5 / scale, graphicsdata.window_size.1 * 0.5 / scale)
 }
 
 const EXTRASIZE: f32 = 2.0;
 const WINDOWSIZE: f32 = 0.75;
 
-fn update_tile_buffer(gfx: &mut Graphics, surface: &Surface, graphicsdata: &mut GraphicsData, scale: f32) {
+fn update_tile_buffer(gfx: &mut Graphics, surface: &Surface, tileman: &TileManager, chart: &Chart, graphicsdata: &mut GraphicsData, scale: f32) {
     let buffersize = (graphicsdata.window_size.0 + EXTRASIZE*scale, graphicsdata.window_size.1 + EXTRASIZE*scale);
     let dpos = surface.camera_pos - graphicsdata.prev_cam_pos;
     let dpos2 = surface.camera_pos - graphicsdata.refreshpos;
@@ -118,7 +118,11 @@ fn update_tile_buffer(gfx: &mut Graphics, surface: &Surface, graphicsdata: &mut
         refresh_tile_buffer(gfx, surface, graphicsdata, &tex.clone(), buffersize, dpos, scale);
     }
     else {
-        redraw_tile_buffer(gfx, surface, graphicsdata, buffersize, scale);
+        redraw_tile_buffer(gfx, surface, tileman, chart, graphicsdata, buffersize, scale);
+    }
+
+    if !graphicsdata.dirty_chunks.is_empty() {
+        redraw_dirty_chunks(gfx, surface, tileman, chart, graphicsdata, buffersize, scale);
     }
 }
 
@@ -140,12 +144,9 @@ fn refresh_tile_buffer(gfx: &mut Graphics, surface: &Surface, graphicsdata: &mut
 
         let center = surface.camera_pos;
 
-        let x0 = center.x - (graphicsdata.window_size.0 * 0.5 / scale);
-        let x1 = center.x + (graphicsdata.window_size.0 * 0.5 / scale);
-        let y0 = center.y - (graphicsdata.window_size.1 * 0.5 / scale);
-        let y1 = center.y + (graphicsdata.window_size.1 * 0.5 / scale);
+        let view = view_bounds(center, graphicsdata, scale);
 
-        println!("({x0},{y0}) - ({x1},{y1})");
+        println!("({},{}) - ({},{})", view.min.x, view.min.y, view.max.x, view.max.y);
 
         //TODO: render new tiles
 
@@ -170,10 +171,12 @@ fn refresh_tile_buffer(gfx: &mut Graphics, surface: &Surface, graphicsdata: &mut
     }
 }
 
-fn redraw_tile_buffer(gfx: &mut Graphics, surface: &Surface, graphicsdata: &mut GraphicsData, buffersize: (f32, f32), scale: f32) {
+///draws the charted chunks in view. uncharted ones are left black
+fn redraw_tile_buffer(gfx: &mut Graphics, surface: &Surface, tileman: &TileManager, chart: &Chart, graphicsdata: &mut GraphicsData, buffersize: (f32, f32), scale: f32) {
     
     let rtex = gfx.create_render_texture(buffersize.0 as u32, buffersize.1 as u32).build().unwrap();
     let mut tdraw =  rtex.create_draw();
+    tdraw.clear(Color::BLACK);
 
     graphicsdata.refreshpos = surface.camera_pos;
     println!("refresh");
@@ -182,38 +185,14 @@ fn redraw_tile_buffer(gfx: &mut Graphics, surface: &Surface, graphicsdata: &mut
 
     let center = surface.camera_pos;
 
-    let x0 = (center.x - EXTRASIZE - (graphicsdata.window_size.0 * 0.5 / scale)).signed_ceil() as i32;
-    let x1 = (center.x + EXTRASIZE + (graphicsdata.window_size.0 * 0.5 / scale)).signed_ceil() as i32;
-    let y0 = (center.y - EXTRASIZE - (graphicsdata.window_size.1 * 0.5 / scale)).signed_ceil() as i32;
-    let y1 = (center.y + EXTRASIZE + (graphicsdata.window_size.1 * 0.5 / scale)).signed_ceil() as i32;
+    let view = view_bounds(center, graphicsdata, scale).expand(EXTRASIZE);
 
-    //println!("({x0},{y0}) - ({x1},{y1})");
+    //println!("{view:?}");
     
-    let c0: ChunkCoord = Coordinate::new(x0 as f32, y0 as f32).into();
-    let c1: ChunkCoord = Coordinate::new(x1 as f32, y1 as f32).into();
-
-    for x in c0.x .. c1.x+1 {
-        for y in c0.y .. c1.y+1 {
-            let c = surface.get_chunk(ChunkCoord::new(x, y));
-            if let Some(chunk) = c {
-                for tx in 0..chunk::CHUNK_SIZE as i32 {
-                    for ty in 0..chunk::CHUNK_SIZE as i32 {
-                        let tile = &chunk.tiles[(tx * chunk::CHUNK_SIZE as i32 + ty) as usize];
-                        let abspos = Coordinate::new((x * chunk::CHUNK_SIZE as i32 + tx) as f32, (y * chunk::CHUNK_SIZE as i32 + ty) as f32);
-                        let relpos = abspos - graphicsdata.prev_cam_pos;
-                        let tcoord = (relpos.x * scale, relpos.y * scale);
-                        if  tcoord.0 - scale > buffersize.0/2.0 || tcoord.0 + scale < -buffersize.0/2.0 ||
-                        tcoord.1 - scale > buffersize.1/2.0 || tcoord.1 + scale < -buffersize.1/2.0 {
-                            continue;
-                        }
-                        
-                        tdraw.image(&tile.texture)
-                        .position(tcoord.0, tcoord.1)
-                        .size(scale, scale);
-                    }
-                }
-            }
-        }
+    graphicsdata.buffer_chunks = view.chunks();
+
+    for chunk in surface.chunks_in(graphicsdata.buffer_chunks).filter(|c| chart.is_charted(c.position)) {
+        draw_chunk(&mut tdraw, chunk, tileman, graphicsdata.prev_cam_pos, buffersize, scale);
     }
 
     gfx.render_to(&rtex, &tdraw);
@@ -222,7 +201,64 @@ fn redraw_tile_buffer(gfx: &mut Graphics, surface: &Surface, graphicsdata: &mut
 
     graphicsdata.tilebuffer = Some(rtex.texture().clone());
     graphicsdata.prev_cam_pos = surface.camera_pos;
+    graphicsdata.dirty_chunks.clear();
+
+}
+
+///draws the dirty chunks over the tile buffer, keeping everything else that is in it
+fn redraw_dirty_chunks(gfx: &mut Graphics, surface: &Surface, tileman: &TileManager, chart: &Chart, graphicsdata: &mut GraphicsData, buffersize: (f32, f32), scale: f32) {
+    let Some(tex) = graphicsdata.tilebuffer.clone() else { return; };
 
+    let rtex = gfx.create_render_texture(buffersize.0 as u32, buffersize.1 as u32).build().unwrap();
+    let mut tdraw =  rtex.create_draw();
+    tdraw.clear(Color::BLACK);
+
+    tdraw.image(&tex)
+    .position(0.0, 0.0)
+    .size(tex.width(), tex.height());
+
+    tdraw.transform().push(Mat3::from_translation(Vec2::new(graphicsdata.window_size.0, graphicsdata.window_size.1) * 0.5));
+
+    let size = chunk::CHUNK_SIZE as f32 * scale;
+    for coord in std::mem::take(&mut graphicsdata.dirty_chunks) {
+        let corner = Coordinate::new((coord.x * chunk::CHUNK_SIZE as i32) as f32, (coord.y * chunk::CHUNK_SIZE as i32) as f32) - graphicsdata.prev_cam_pos;
+        tdraw.rect((corner.x * scale, corner.y * scale), (size, size))
+        .fill_color(Color::BLACK);
+
+        let Some(chunk) = surface.get_chunk(coord).filter(|c| chart.is_charted(c.position)) else { continue; };
+        draw_chunk(&mut tdraw, chunk, tileman, graphicsdata.prev_cam_pos, buffersize, scale);
+    }
+
+    gfx.render_to(&rtex, &tdraw);
+    graphicsdata.tilebuffer = Some(rtex.texture().clone());
+}
+
+///draws the top tiles of chunk into a buffer centered on origin. tiles outside the buffer are skipped
+fn draw_chunk(tdraw: &mut Draw, chunk: &chunk::Chunk, tileman: &TileManager, origin: Coordinate, buffersize: (f32, f32), scale: f32) {
+    let (x, y) = (chunk.position.x, chunk.position.y);
+    for tx in 0..chunk::CHUNK_SIZE as i32 {
+        for ty in 0..chunk::CHUNK_SIZE as i32 {
+            let tile = chunk.top_tile((tx * chunk::CHUNK_SIZE as i32 + ty) as usize);
+            let abspos = Coordinate::new((x * chunk::CHUNK_SIZE as i32 + tx) as f32, (y * chunk::CHUNK_SIZE as i32 + ty) as f32);
+            let relpos = abspos - origin;
+            let tcoord = (relpos.x * scale, relpos.y * scale);
+            if  tcoord.0 - scale > buffersize.0/2.0 || tcoord.0 + scale < -buffersize.0/2.0 ||
+            tcoord.1 - scale > buffersize.1/2.0 || tcoord.1 + scale < -buffersize.1/2.0 {
+                continue;
+            }
+
+            if let Some(texture) = tileman.texture(tile) {
+                tdraw.image(texture)
+                .position(tcoord.0, tcoord.1)
+                .size(scale, scale);
+            }
+            else if let Some(props) = tileman.properties(tile) {
+                let c = props.map_color;
+                tdraw.rect(tcoord, (scale, scale))
+                .fill_color(Color::from_bytes(c.r, c.g, c.b, 255));
+            }
+        }
+    }
 }
 
 fn draw_tiles(gfx: &mut Graphics, surface: &Surface, graphicsdata: &mut GraphicsData, scale: f32) {
@@ -306,12 +342,9 @@ fn draw_grid(gfx: &mut Graphics, surface: &Surface, graphicsdata: &mut GraphicsD
 
     let center = surface.camera_pos;
 
-    let x0 = (center.x - (graphicsdata.window_size.0 * 0.5 / scale)) as i32;
-    let x1 = (center.x + (graphicsdata.window_size.0 * 0.5 / scale)) as i32;
-    let _y0 = (center.y - (graphicsdata.window_size.1 * 0.5 / scale)) as i32;
-    let _y1 = (center.y + (graphicsdata.window_size.1 * 0.5 / scale)) as i32;
+    let view = view_bounds(center, graphicsdata, scale).tiles();
 
-    for _x in x0..x1 {
+    for _x in view.min.x..=view.max.x {
         
     }
 
@@ -322,11 +355,11 @@ fn draw_grid(gfx: &mut Graphics, surface: &Surface, graphicsdata: &mut GraphicsD
 
 
 
-pub fn render_surface(gfx: &mut Graphics, surface: &Surface, graphicsdata: &mut GraphicsData) {
+pub fn render_surface(gfx: &mut Graphics, surface: &Surface, tileman: &TileManager, chart: &Chart, graphicsdata: &mut GraphicsData) {
 
     //min zoom = 10, max zoom = 100
     let scale = 10.0;
-    update_tile_buffer(gfx, surface, graphicsdata, scale);
+    update_tile_buffer(gfx, surface, tileman, chart, graphicsdata, scale);
 
     draw_tiles(gfx, surface, graphicsdata, scale);
     draw_entities(gfx, surface, graphicsdata, scale);
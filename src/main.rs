@@ -1,10 +1,10 @@
 use core::f32;
-use std::time::Instant;
+use std::{sync::Arc, time::Instant};
 
 use graphics::GraphicsData;
 use notan::{draw::DrawConfig, prelude::*};
 use prototype::{EntityManager, PrototypeManager};
-use world::{tile::TileManager, ChunkCoord, Surface};
+use world::{area::ChunkArea, chart::Chart, force::{ForceId, ForceManager}, genpool::GenPool, scriptgen::ScriptGen, settings::MapGenSettings, surfaces::SurfaceManager, tile::{MapColor, TileManager, TileProperties}, ChunkCoord, Surface, SurfaceEvent, TickStats};
 
 mod graphics;
 mod world;
@@ -13,19 +13,28 @@ mod prototype;
 
 
 enum Task{
-    GenChunk(ChunkCoord),
-    RemoveEntity(u32),
-    PlaceEntity(u32, world::entity::Entity),
+    ///generate a chunk on the named surface
+    GenChunk(String, ChunkCoord),
+    RemoveEntity(String, world::entity::EID),
+    PlaceEntity(String, world::entity::Entity),
+    ///writes the game to SAVE_DIR
+    Save,
 
 }
 
 struct UpdateData {
     pub update_time: f32,
+    ///chunk activity of the last tick, summed over all surfaces
+    pub tick_stats: TickStats,
+    ///updates since the game started
+    pub updates: u64,
 }
 impl UpdateData {
     pub fn new() -> Self {
         Self {
             update_time: 0.0,
+            tick_stats: TickStats::default(),
+            updates: 0,
         }
     }
 }
@@ -34,7 +43,9 @@ impl UpdateData {
 
 #[derive(Copy, Clone, Default)]
 struct DebugSettings{
-    pub chunk_border: bool
+    pub chunk_border: bool,
+    ///logs how many chunks are ticked and sleeping whenever that changes
+    pub tick_stats: bool,
 }
 
 #[derive(Copy, Clone, Default)]
@@ -46,30 +57,104 @@ struct Options {
 #[derive(AppState)]
 #[allow(dead_code)]
 struct State {
-    tileman: TileManager,
+    ///shared with the chunk generation threads, so it can't change once the game runs
+    tileman: Arc<TileManager>,
     protoman: PrototypeManager,
     entityman: EntityManager,
     //recipeman: RecipeManager,
     //scriptman: ScriptManager,
 
-    surface: Surface,
+    forces: ForceManager,
+    ///settings the main surface was generated with
+    mapgen: MapGenSettings,
+    surfaces: SurfaceManager,
+    ///name of the surface the camera is looking at
+    active_surface: String,
+    ///the force whose chart is drawn. everything it hasn't seen is black
+    view_force: ForceId,
     
     options: Options,
     graphicsdata: GraphicsData,
     updatedata: UpdateData,
 
     tasks: Vec<Task>,
+    genpool: GenPool,
 }
 
 
+///the tile and decorative types of the game, without their textures
+fn tile_types() -> TileManager {
+    let mut tileman = TileManager::new();
+
+    tileman.add_tile("grass", TileProperties {
+        pollution_absorption: 0.0000075,
+        map_color: MapColor::new(63, 105, 38),
+        ..Default::default()
+    });
+    tileman.add_tile("grass1", TileProperties {
+        pollution_absorption: 0.0000075,
+        map_color: MapColor::new(72, 112, 42),
+        ..Default::default()
+    });
+    tileman.add_tile("sand", TileProperties {
+        map_color: MapColor::new(206, 178, 112),
+        ..Default::default()
+    });
+    tileman.add_tile("dirt", TileProperties {
+        pollution_absorption: 0.000005,
+        map_color: MapColor::new(125, 92, 58),
+        ..Default::default()
+    });
+    tileman.add_tile("shore", TileProperties {
+        speed_modifier: 0.9,
+        map_color: MapColor::new(170, 150, 100),
+        ..Default::default()
+    });
+    tileman.add_tile("water", TileProperties {
+        walkable: false,
+        buildable: false,
+        water: true,
+        map_color: MapColor::new(51, 83, 95),
+        ..Default::default()
+    });
+    tileman.add_tile("deepwater", TileProperties {
+        walkable: false,
+        buildable: false,
+        water: true,
+        map_color: MapColor::new(38, 64, 73),
+        ..Default::default()
+    });
+    for decorative in ["grass-tuft", "flowers", "sand-ripples", "pebbles", "dry-grass"] {
+        tileman.add_decorative(decorative);
+    }
+
+    tileman
+}
+
+///the tile and decorative types of the game, with textures
+fn register_tiles(gfx: &mut Graphics) -> TileManager {
+    let mut tileman = tile_types();
+    for (tile, texture) in [("grass", r"assets\grass.jpg"), ("grass1", r"assets\grass1.jpg")] {
+        let id = tileman.get_id(tile).unwrap();
+        tileman.load_texture(id, texture, gfx);
+    }
+    tileman
+}
+
 impl State {
-    fn new(_gfx: &mut Graphics) -> Self {
+    fn new(gfx: &mut Graphics) -> Self {
+        let tileman = Arc::new(register_tiles(gfx));
         Self {
-            tileman: world::tile::TileManager::new(),
+            genpool: GenPool::with_default_threads(tileman.clone()),
+            tileman,
             protoman: PrototypeManager::new(),
             entityman: EntityManager::new(),
 
-            surface: world::Surface::new(world::worldgen::LabGen{}),
+            forces: ForceManager::new(),
+            mapgen: MapGenSettings::default(),
+            surfaces: SurfaceManager::new(),
+            active_surface: String::from("main"),
+            view_force: ForceId::PLAYER,
             
             
             options: Options::default(),
@@ -88,6 +173,10 @@ impl State {
 
 #[notan_main]
 fn main() -> Result<(), String> {
+    if has_flag("--preview") {
+        let path = arg_value("--preview").ok_or("usage: --preview <path> [--preview-radius <chunks>]")?;
+        return write_preview(&path);
+    }
 
     let windowconfig = WindowConfig::new().set_title("FactoryGame").set_size(1280, 720);
 
@@ -100,30 +189,166 @@ fn main() -> Result<(), String> {
     .build()
 }
 
+///map generation settings of the main surface
+const MAPGEN_CONFIG: &str = "mapgen.json";
+///where the game is saved. only the charts of the forces are saved so far
+const SAVE_DIR: &str = "save";
+///updates between autosaves, about five minutes
+const AUTOSAVE_INTERVAL: u64 = 60 * 60 * 5;
+
+fn has_flag(flag: &str) -> bool {
+    std::env::args().any(|a| a == flag)
+}
+
+///the argument following flag on the command line, None if flag isn't there or has no value
+fn arg_value(flag: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    let pos = args.iter().position(|a| a == flag)?;
+    match args.get(pos + 1).filter(|value| !value.starts_with("--")) {
+        Some(value) => Some(value.clone()),
+        None => {
+            println!("{flag} needs a value");
+            None
+        }
+    }
+}
+
+///the settings for the main surface: from a map exchange string passed as
+///`--map-string <string>`, otherwise from MAPGEN_CONFIG
+fn load_mapgen_settings() -> MapGenSettings {
+    if let Some(text) = arg_value("--map-string") {
+        match MapGenSettings::from_exchange_string(&text) {
+            Ok(settings) => return settings,
+            Err(e) => println!("Invalid map exchange string: {e}"),
+        }
+    }
+
+    MapGenSettings::load(MAPGEN_CONFIG).unwrap_or_else(|e| {
+        println!("Could not load {MAPGEN_CONFIG}, using default map settings: {e}");
+        MapGenSettings::default()
+    })
+}
+
+fn print_exchange_string(settings: &MapGenSettings) {
+    match settings.to_exchange_string() {
+        Ok(text) => println!("Map exchange string: {text}"),
+        Err(e) => println!("The map settings can't be shared: {e}"),
+    }
+}
+
 fn setup(gfx: &mut Graphics) -> State {
 
     let mut state = State::new(gfx);
     state.options.debugsettings.chunk_border = true;
+    state.options.debugsettings.tick_stats = true;
 
 
-    state.tileman.register_tile("grass", r"assets\grass.jpg", gfx);
-    state.tileman.register_tile("grass1", r"assets\grass1.jpg", gfx);
+    state.mapgen = load_mapgen_settings();
+    print_exchange_string(&state.mapgen);
 
-    let size = 10;
-
-    for x in -size..size {
-        for y in -size..size {
-            state.add_task(Task::GenChunk(ChunkCoord::new(x,y)));
-        }
+    if let Err(e) = create_main_surface(&mut state.surfaces, &state.mapgen, &state.tileman, load_worldgen_script()) {
+        panic!("Could not create the main surface: {e}");
+    }
+    if let Some(force) = state.forces.get_mut(ForceId::PLAYER) {
+        force.chart_area("main", ChunkArea::around(ChunkCoord::new(0, 0), world::LOAD_RADIUS), 0);
+    }
+    if let Err(e) = load_game(&mut state) {
+        println!("Could not load the saved game: {e}");
     }
 
-    
-    //state.add_task(Task::GenChunk(ChunkCoord::new(0, 0)));
-    println!("{} chunks", state.surface.chunks.len());
+    stream_chunks(&mut state);
     
     return state;
 }
 
+///writes what is kept between sessions to SAVE_DIR
+fn save_game(state: &State) -> Result<(), String> {
+    state.forces.save_charts(SAVE_DIR).map_err(|e| e.to_string())
+}
+
+///restores what save_game wrote, if the game was saved before
+fn load_game(state: &mut State) -> Result<(), String> {
+    if !std::path::Path::new(SAVE_DIR).exists() {
+        return Ok(());
+    }
+    state.forces.load_charts(SAVE_DIR).map_err(|e| e.to_string())
+}
+
+///the world generator script passed as `--worldgen-script <path>`, if there is one
+fn load_worldgen_script() -> Option<ScriptGen> {
+    let path = arg_value("--worldgen-script")?;
+    match ScriptGen::load(&path) {
+        Ok(script) => Some(script),
+        Err(e) => {
+            println!("Could not load world generator script, using the default generator: {e}");
+            None
+        }
+    }
+}
+
+///creates the main surface with its generator, the stamps of the settings and the generation
+///stages. a script replaces the terrain, trees and cliffs, resources and stamps still come from
+///the settings.
+///fails if tiles the generators need aren't registered in tileman
+fn create_main_surface<'a>(surfaces: &'a mut SurfaceManager, settings: &MapGenSettings, tileman: &TileManager, script: Option<ScriptGen>) -> Result<&'a mut Surface, String> {
+    let seed = settings.seed;
+    let stamps = settings.tile_stamps(tileman)?;
+    if let Some(script) = script {
+        let entities = script.entities(seed);
+        let main = surfaces.create("main", script).map_err(|_| "there already is a main surface".to_owned())?;
+        main.seed = seed;
+        main.limits = settings.limits();
+        for stamp in stamps {
+            main.add_overlay(stamp);
+        }
+        main.add_stage(world::resources::OreGen::from_settings(settings));
+        main.add_stage(entities);
+        return Ok(main);
+    }
+
+    let terrain = world::terrain::TerrainGen::from_settings(settings, tileman)?;
+    let (cliffs, trees, decoratives) = (terrain.cliffs(seed), terrain.trees(seed), terrain.decoratives(seed, tileman)?);
+    let main = surfaces.create("main", terrain).map_err(|_| "there already is a main surface".to_owned())?;
+    main.seed = seed;
+    main.limits = settings.limits();
+    for stamp in stamps {
+        main.add_overlay(stamp);
+    }
+    main.add_stage(world::resources::OreGen::from_settings(settings));
+    main.add_stage(decoratives);
+    main.add_stage(cliffs);
+    main.add_stage(trees);
+    Ok(main)
+}
+
+///radius in chunks around spawn drawn by --preview, unless --preview-radius is given
+const PREVIEW_RADIUS: i32 = 8;
+
+///generates the area around spawn of the main surface and writes it to path as a png,
+///without opening a window. used as `--preview <path> [--preview-radius <chunks>]`
+fn write_preview(path: &str) -> Result<(), String> {
+    let settings = load_mapgen_settings();
+    let radius = match arg_value("--preview-radius") {
+        Some(r) => r.parse().map_err(|_| format!("invalid preview radius {r}"))?,
+        None => PREVIEW_RADIUS,
+    };
+    print_exchange_string(&settings);
+
+    let tileman = tile_types();
+    let mut surfaces = SurfaceManager::new();
+    let main = create_main_surface(&mut surfaces, &settings, &tileman, load_worldgen_script())?;
+
+    let before = Instant::now();
+    let area = ChunkArea::around(ChunkCoord::new(0, 0), radius);
+    let chunks = world::preview::generate(main, area, &tileman);
+    let image = world::preview::render(main, area.tiles(), &tileman, &settings.resource_specs());
+    image.write_png(path)?;
+
+    println!("Generated {chunks} chunks in {}ms, wrote {}x{} preview to {path}",
+        before.elapsed().as_millis(), image.width, image.height);
+    Ok(())
+}
+
 fn init(_state: &mut State) {
 
     
@@ -136,8 +361,17 @@ fn init(_state: &mut State) {
 fn draw(_app: &mut App, _assets: &mut Assets, gfx: &mut Graphics, state: &mut State) {
     
     let before = Instant::now();
-   
-    graphics::render_surface(gfx, &state.surface, &mut state.graphicsdata);
+
+    let Some(surface) = state.surfaces.get_mut(&state.active_surface) else { return; };
+    let dirty = surface.take_dirty_chunks();
+    graphics::invalidate_chunks(&mut state.graphicsdata, &dirty);
+
+    let surface = &*surface;
+    let empty = Chart::new();
+    let chart = state.forces.get(state.view_force)
+        .and_then(|f| f.chart_of(&state.active_surface))
+        .unwrap_or(&empty);
+    graphics::render_surface(gfx, surface, &state.tileman, chart, &mut state.graphicsdata);
 
 
     println!("time: {}us", before.elapsed().as_micros());
@@ -163,6 +397,30 @@ fn update(app: &mut App, state: &mut State) {
         else { break; }
     }
 
+    for generated in state.genpool.poll() {
+        let Some(s) = state.surfaces.get_mut(&generated.surface) else { continue; };
+        let position = generated.chunk.position;
+        if let Some(e) = &generated.failed {
+            println!("Generating chunk {:?} on {} failed, filled it with the default tile: {e}", position, generated.surface);
+        }
+        if s.insert_generated(generated.chunk) {
+            println!("Generated chunk {:?} on {}", position, generated.surface);
+        }
+    }
+
+    for surface in state.surfaces.iter_mut() {
+        surface.advance_generation(&state.tileman);
+    }
+
+    let mut events = vec![];
+    for surface in state.surfaces.iter_mut() {
+        let name = surface.name().to_owned();
+        events.extend(surface.take_events().into_iter().map(|e| (name.clone(), e)));
+    }
+    for (surface, event) in events {
+        handle_surface_event(&surface, event, state);
+    }
+
 
     // update each 300ms
     if state.updatedata.update_time >= 0.016 {
@@ -175,27 +433,91 @@ fn update(app: &mut App, state: &mut State) {
     let speed = 0.125;
     let rad = 11.0;
 
-    state.surface.camera_pos.x = (app.timer.elapsed().as_secs_f32() * speed * f32::consts::PI).cos() * rad;
-    state.surface.camera_pos.y = (app.timer.elapsed().as_secs_f32() * speed * f32::consts::PI).sin() * rad;
-    //state.surface.camera_pos.x = app.timer.elapsed().as_secs_f32() * rad;
+    if let Some(surface) = state.surfaces.get_mut(&state.active_surface) {
+        surface.camera_pos.x = (app.timer.elapsed().as_secs_f32() * speed * f32::consts::PI).cos() * rad;
+        surface.camera_pos.y = (app.timer.elapsed().as_secs_f32() * speed * f32::consts::PI).sin() * rad;
+    }
+    //surface.camera_pos.x = app.timer.elapsed().as_secs_f32() * rad;
+
+    stream_chunks(state);
+
 
+    let mut stats = TickStats::default();
+    for surface in state.surfaces.iter_mut() {
+        stats += surface.update();
+    }
+    if state.options.debugsettings.tick_stats && stats != state.updatedata.tick_stats {
+        println!("active chunks: {}, sleeping: {}", stats.active_chunks, stats.sleeping_chunks);
+    }
+    state.updatedata.tick_stats = stats;
 
+    chart_surfaces(state);
 
-    for chunk in &mut state.surface.chunks {
-        chunk.update();
+    state.updatedata.updates += 1;
+    if state.updatedata.updates.is_multiple_of(AUTOSAVE_INTERVAL) {
+        state.add_task(Task::Save);
     }
 
 
 }
 
 
+///queues generation of the chunks that came into range of the camera or a player, on every surface
+fn stream_chunks(state: &mut State) {
+    let mut tasks = vec![];
+    for surface in state.surfaces.iter_mut() {
+        // tasks are popped from the back, so queue the closest chunks last
+        for coord in surface.stream_chunks(&state.tileman).into_iter().rev() {
+            tasks.push(Task::GenChunk(surface.name().to_owned(), coord));
+        }
+    }
+    for task in tasks {
+        state.add_task(task);
+    }
+}
+
+///charts what every force can currently see and redraws chunks that came out of the fog
+fn chart_surfaces(state: &mut State) {
+    for surface in state.surfaces.iter() {
+        for (force, area) in surface.chart_sources() {
+            let Some(f) = state.forces.get_mut(force) else { continue; };
+            let charted = f.chart_area(surface.name(), area, surface.tick());
+            if force == state.view_force && surface.name() == state.active_surface {
+                graphics::invalidate_chunks(&mut state.graphicsdata, &charted);
+            }
+        }
+    }
+}
+
+fn handle_surface_event(_surface: &str, event: SurfaceEvent, _state: &mut State) {
+    match event {
+        //nothing caches tile data outside the renderer yet
+        SurfaceEvent::TilesChanged(_tiles) => {},
+    }
+}
+
 fn handle_task(task: Task, state: &mut State) {
     match task {
-        Task::GenChunk(position) => {
-            println!("Generating chunk {:?}", position);
-            state.surface.gen_chunk(position, &state.tileman);
+        Task::GenChunk(surface, position) => {
+            let Some(s) = state.surfaces.get_mut(&surface) else { return; };
+            if !s.restore_chunk(position, &state.tileman) {
+                state.genpool.submit(&surface, position, s.seed, s.generator());
+            }
+        },
+        Task::RemoveEntity(surface, id) => {
+            if let Some(s) = state.surfaces.get_mut(&surface) {
+                s.remove_entity(id);
+            }
+        },
+        Task::PlaceEntity(surface, entity) => {
+            if let Some(s) = state.surfaces.get_mut(&surface) {
+                s.place_entity(entity);
+            }
+        },
+        Task::Save => {
+            if let Err(e) = save_game(state) {
+                println!("Could not save the game: {e}");
+            }
         },
-        Task::RemoveEntity(_) => todo!(),
-        Task::PlaceEntity(_, _entity) => todo!(),
     }
 }
\ No newline at end of file
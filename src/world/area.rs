@@ -0,0 +1,337 @@
+use super::{chunk::CHUNK_SIZE, ChunkCoord, Coordinate, TileCoord};
+
+///defines a rectangle of integer coordinates from min to max (both inclusive).
+///an area with min > max on either axis is empty
+macro_rules! int_area {
+    ($(#[$meta:meta])* $name:ident, $coord:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+        pub struct $name { pub min: $coord, pub max: $coord }
+
+        impl $name {
+            ///the area spanned by two corners, in any order
+            pub fn new(a: $coord, b: $coord) -> Self {
+                Self {
+                    min: $coord::new(a.x.min(b.x), a.y.min(b.y)),
+                    max: $coord::new(a.x.max(b.x), a.y.max(b.y)),
+                }
+            }
+
+            ///square area reaching radius coordinates out from center in every direction
+            pub fn around(center: $coord, radius: i32) -> Self {
+                Self {
+                    min: $coord::new(center.x - radius, center.y - radius),
+                    max: $coord::new(center.x + radius, center.y + radius),
+                }
+            }
+
+            pub fn empty() -> Self {
+                Self { min: $coord::new(0, 0), max: $coord::new(-1, -1) }
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.min.x > self.max.x || self.min.y > self.max.y
+            }
+
+            #[allow(dead_code)]
+            pub fn width(&self) -> i32 {
+                (self.max.x - self.min.x + 1).max(0)
+            }
+
+            #[allow(dead_code)]
+            pub fn height(&self) -> i32 {
+                (self.max.y - self.min.y + 1).max(0)
+            }
+
+            #[allow(dead_code)]
+            pub fn len(&self) -> usize {
+                self.width() as usize * self.height() as usize
+            }
+
+            #[allow(dead_code)]
+            pub fn contains(&self, coord: $coord) -> bool {
+                coord.x >= self.min.x && coord.x <= self.max.x &&
+                coord.y >= self.min.y && coord.y <= self.max.y
+            }
+
+            #[allow(dead_code)]
+            pub fn contains_area(&self, other: &Self) -> bool {
+                other.is_empty() || (self.contains(other.min) && self.contains(other.max))
+            }
+
+            #[allow(dead_code)]
+            pub fn intersects(&self, other: &Self) -> bool {
+                self.intersection(other).is_some()
+            }
+
+            #[allow(dead_code)]
+            pub fn intersection(&self, other: &Self) -> Option<Self> {
+                let area = Self {
+                    min: $coord::new(self.min.x.max(other.min.x), self.min.y.max(other.min.y)),
+                    max: $coord::new(self.max.x.min(other.max.x), self.max.y.min(other.max.y)),
+                };
+                if area.is_empty() { None } else { Some(area) }
+            }
+
+            ///smallest area containing both
+            #[allow(dead_code)]
+            pub fn union(&self, other: &Self) -> Self {
+                if self.is_empty() { return *other; }
+                if other.is_empty() { return *self; }
+                Self {
+                    min: $coord::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y)),
+                    max: $coord::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y)),
+                }
+            }
+
+            ///grows the area by amount on every side. negative amounts shrink it
+            #[allow(dead_code)]
+            pub fn expand(&self, amount: i32) -> Self {
+                Self {
+                    min: $coord::new(self.min.x - amount, self.min.y - amount),
+                    max: $coord::new(self.max.x + amount, self.max.y + amount),
+                }
+            }
+
+            ///every coordinate in the area, row by row
+            pub fn iter(&self) -> impl Iterator<Item = $coord> {
+                let (min, max) = (self.min, self.max);
+                (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| $coord::new(x, y)))
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::empty()
+            }
+        }
+    };
+}
+
+int_area!(
+    ///rectangle of tiles, both corners inclusive
+    TileArea, TileCoord
+);
+
+int_area!(
+    ///rectangle of chunks, both corners inclusive
+    ChunkArea, ChunkCoord
+);
+
+impl TileArea {
+    ///moves the area by offset
+    pub fn translate(&self, offset: TileCoord) -> Self {
+        if self.is_empty() { return *self; }
+        TileArea { min: self.min.offset(offset.x, offset.y), max: self.max.offset(offset.x, offset.y) }
+    }
+
+    ///the chunks that contain at least one tile of this area
+    pub fn chunks(&self) -> ChunkArea {
+        if self.is_empty() {
+            return ChunkArea::empty();
+        }
+        ChunkArea { min: self.min.into(), max: self.max.into() }
+    }
+}
+
+impl ChunkArea {
+    ///every tile inside these chunks
+    pub fn tiles(&self) -> TileArea {
+        if self.is_empty() {
+            return TileArea::empty();
+        }
+        let max: TileCoord = self.max.into();
+        TileArea {
+            min: self.min.into(),
+            max: TileCoord::new(max.x + CHUNK_SIZE as i32 - 1, max.y + CHUNK_SIZE as i32 - 1),
+        }
+    }
+}
+
+impl From<ChunkCoord> for TileArea {
+    fn from(value: ChunkCoord) -> Self {
+        ChunkArea::new(value, value).tiles()
+    }
+}
+
+impl From<ChunkArea> for TileArea {
+    fn from(value: ChunkArea) -> Self {
+        value.tiles()
+    }
+}
+
+impl From<TileArea> for ChunkArea {
+    fn from(value: TileArea) -> Self {
+        value.chunks()
+    }
+}
+
+
+///axis aligned box in world space, from min to max
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct BoundingBox { pub min: Coordinate, pub max: Coordinate }
+
+impl BoundingBox {
+    ///the box spanned by two corners, in any order
+    #[allow(dead_code)]
+    pub fn new(a: Coordinate, b: Coordinate) -> Self {
+        Self {
+            min: Coordinate::new(a.x.min(b.x), a.y.min(b.y)),
+            max: Coordinate::new(a.x.max(b.x), a.y.max(b.y)),
+        }
+    }
+
+    ///box of the given half width and half height around center
+    pub fn around(center: Coordinate, half_width: f32, half_height: f32) -> Self {
+        Self {
+            min: Coordinate::new(center.x - half_width, center.y - half_height),
+            max: Coordinate::new(center.x + half_width, center.y + half_height),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn width(&self) -> f32 {
+        (self.max.x - self.min.x).max(0.0)
+    }
+
+    #[allow(dead_code)]
+    pub fn height(&self) -> f32 {
+        (self.max.y - self.min.y).max(0.0)
+    }
+
+    #[allow(dead_code)]
+    pub fn center(&self) -> Coordinate {
+        Coordinate::new((self.min.x + self.max.x) * 0.5, (self.min.y + self.max.y) * 0.5)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x >= self.max.x || self.min.y >= self.max.y
+    }
+
+    #[allow(dead_code)]
+    pub fn contains(&self, coord: Coordinate) -> bool {
+        coord.x >= self.min.x && coord.x < self.max.x &&
+        coord.y >= self.min.y && coord.y < self.max.y
+    }
+
+    #[allow(dead_code)]
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    #[allow(dead_code)]
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let bbox = Self {
+            min: Coordinate::new(self.min.x.max(other.min.x), self.min.y.max(other.min.y)),
+            max: Coordinate::new(self.max.x.min(other.max.x), self.max.y.min(other.max.y)),
+        };
+        if bbox.is_empty() { None } else { Some(bbox) }
+    }
+
+    ///grows the box by amount on every side. negative amounts shrink it
+    pub fn expand(&self, amount: f32) -> Self {
+        Self {
+            min: Coordinate::new(self.min.x - amount, self.min.y - amount),
+            max: Coordinate::new(self.max.x + amount, self.max.y + amount),
+        }
+    }
+
+    ///moves the box by offset
+    #[allow(dead_code)]
+    pub fn translate(&self, offset: Coordinate) -> Self {
+        Self { min: self.min + offset, max: self.max + offset }
+    }
+
+    ///the tiles this box overlaps
+    pub fn tiles(&self) -> TileArea {
+        if self.is_empty() {
+            return TileArea::empty();
+        }
+        TileArea {
+            min: self.min.into(),
+            max: TileCoord::new(self.max.x.ceil() as i32 - 1, self.max.y.ceil() as i32 - 1),
+        }
+    }
+
+    ///the chunks this box overlaps
+    pub fn chunks(&self) -> ChunkArea {
+        self.tiles().chunks()
+    }
+}
+
+impl From<TileArea> for BoundingBox {
+    fn from(value: TileArea) -> Self {
+        if value.is_empty() {
+            return BoundingBox::default();
+        }
+        BoundingBox {
+            min: value.min.into(),
+            max: Coordinate::new((value.max.x + 1) as f32, (value.max.y + 1) as f32),
+        }
+    }
+}
+
+impl From<ChunkArea> for BoundingBox {
+    fn from(value: ChunkArea) -> Self {
+        value.tiles().into()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(x0: i32, y0: i32, x1: i32, y1: i32) -> TileArea {
+        TileArea::new(TileCoord::new(x0, y0), TileCoord::new(x1, y1))
+    }
+
+    #[test]
+    fn tile_area_intersection() {
+        let a = area(0, 0, 4, 4);
+        assert_eq!(a.intersection(&area(3, 2, 8, 3)), Some(area(3, 2, 4, 3)));
+        assert_eq!(a.intersection(&area(-2, -2, 0, 0)), Some(area(0, 0, 0, 0)));
+        assert_eq!(a.intersection(&area(1, 1, 2, 2)), Some(area(1, 1, 2, 2)));
+
+        //areas sharing an edge but no tile don't intersect
+        assert_eq!(a.intersection(&area(5, 0, 6, 1)), None);
+        assert!(!a.intersects(&area(0, 5, 4, 9)));
+        assert!(!a.intersects(&TileArea::empty()));
+        assert!(a.intersects(&area(4, 4, 4, 4)));
+    }
+
+    #[test]
+    fn tile_area_containment() {
+        let a = area(-2, -2, 2, 1);
+        assert!(a.contains(TileCoord::new(-2, -2)));
+        assert!(a.contains(TileCoord::new(2, 1)));
+        assert!(!a.contains(TileCoord::new(2, 2)));
+        assert!(!a.contains(TileCoord::new(-3, 0)));
+        assert!(!TileArea::empty().contains(TileCoord::new(0, 0)));
+
+        assert!(a.contains_area(&a));
+        assert!(a.contains_area(&area(0, 0, 1, 1)));
+        assert!(a.contains_area(&TileArea::empty()));
+        assert!(!a.contains_area(&area(0, 0, 3, 1)));
+        assert!(!area(0, 0, 1, 1).contains_area(&a));
+        assert_eq!(a.len(), 20);
+    }
+
+    #[test]
+    fn bounding_box_intersection_and_containment() {
+        let a = BoundingBox::new(Coordinate::new(0.0, 0.0), Coordinate::new(2.0, 2.0));
+        let b = BoundingBox::new(Coordinate::new(1.5, 1.0), Coordinate::new(3.0, 4.0));
+        assert_eq!(a.intersection(&b), Some(BoundingBox::new(Coordinate::new(1.5, 1.0), Coordinate::new(2.0, 2.0))));
+
+        //boxes that only touch don't intersect, and max is outside the box
+        let touching = BoundingBox::new(Coordinate::new(2.0, 0.0), Coordinate::new(3.0, 2.0));
+        assert!(!a.intersects(&touching));
+        assert!(a.contains(Coordinate::new(0.0, 1.99)));
+        assert!(!a.contains(Coordinate::new(2.0, 1.0)));
+
+        //the tiles of a box are the ones it overlaps
+        assert_eq!(a.tiles(), area(0, 0, 1, 1));
+        assert_eq!(b.tiles(), area(1, 1, 2, 3));
+        assert_eq!(BoundingBox::from(area(0, 0, 1, 1)), a);
+    }
+}
@@ -0,0 +1,157 @@
+use super::{noise, tile::{TileId, TileManager}};
+
+///a kind of terrain. a tile gets the biome whose climate is closest to the climate at that tile
+#[derive(Debug, Clone, PartialEq)]
+pub struct Biome {
+    pub name: String,
+    ///ideal temperature, moisture and elevation, each in -1..=1
+    pub temperature: f64,
+    pub moisture: f64,
+    pub elevation: f64,
+    ///tile names and their relative weights
+    pub tiles: Vec<(String, u32)>,
+    ///chance of a tree on a tile where the forest noise is strongest
+    pub trees: f64,
+    ///chance of a rock on a tile
+    pub rocks: f64,
+    ///decorative names and their chance per tile
+    pub decoratives: Vec<(String, f64)>,
+}
+
+impl Biome {
+    pub fn new(name: &str, temperature: f64, moisture: f64, elevation: f64, tiles: &[(&str, u32)]) -> Self {
+        Self {
+            name: name.to_owned(),
+            temperature,
+            moisture,
+            elevation,
+            tiles: tiles.iter().map(|(t, w)| (t.to_string(), *w)).collect(),
+            trees: 0.0,
+            rocks: 0.0,
+            decoratives: vec![],
+        }
+    }
+
+    pub fn with_trees(mut self, trees: f64) -> Self {
+        self.trees = trees;
+        self
+    }
+
+    pub fn with_rocks(mut self, rocks: f64) -> Self {
+        self.rocks = rocks;
+        self
+    }
+
+    pub fn with_decoratives(mut self, decoratives: &[(&str, f64)]) -> Self {
+        self.decoratives = decoratives.iter().map(|(d, c)| (d.to_string(), *c)).collect();
+        self
+    }
+
+    fn distance(&self, temperature: f64, moisture: f64, elevation: f64) -> f64 {
+        let (dt, dm, de) = (self.temperature - temperature, self.moisture - moisture, self.elevation - elevation);
+        dt * dt + dm * dm + de * de
+    }
+}
+
+///the biomes a generator picks from
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BiomeTable {
+    pub biomes: Vec<Biome>,
+}
+
+impl BiomeTable {
+    pub fn new() -> Self {
+        Self { biomes: vec![] }
+    }
+
+    pub fn add(&mut self, biome: Biome) -> &mut Self {
+        self.biomes.push(biome);
+        self
+    }
+
+    ///the biome closest to the given climate. None if the table is empty
+    pub fn select(&self, temperature: f64, moisture: f64, elevation: f64) -> Option<&Biome> {
+        self.select_index(temperature, moisture, elevation).map(|i| &self.biomes[i])
+    }
+
+    ///index of the biome closest to the given climate. None if the table is empty
+    pub fn select_index(&self, temperature: f64, moisture: f64, elevation: f64) -> Option<usize> {
+        (0..self.biomes.len()).min_by(|a, b| {
+            let da = self.biomes[*a].distance(temperature, moisture, elevation);
+            da.total_cmp(&self.biomes[*b].distance(temperature, moisture, elevation))
+        })
+    }
+
+    ///looks up the tile names so generators don't have to do it for every tile. fails if the
+    ///table is empty, a tile isn't registered or a biome has no tile with a positive weight
+    pub fn resolve(&self, tileman: &TileManager) -> Result<ResolvedBiomes, String> {
+        if self.biomes.is_empty() {
+            return Err("the biome table is empty".to_owned());
+        }
+        let mut choices = vec![];
+        for biome in &self.biomes {
+            let mut tiles = vec![];
+            for (name, weight) in biome.tiles.iter().filter(|(_, w)| *w > 0) {
+                let id = tileman.get_id(name)
+                    .map_err(|_| format!("biome {} uses tile \"{name}\", which isn't registered", biome.name))?;
+                tiles.push((id, *weight));
+            }
+            if tiles.is_empty() {
+                return Err(format!("biome {} has no tiles", biome.name));
+            }
+            choices.push(tiles);
+        }
+        Ok(ResolvedBiomes { table: self.clone(), choices })
+    }
+}
+
+impl BiomeTable {
+    ///desert, grassland and dirt. needs the sand, dirt, grass and grass1 tiles, and the
+    ///grass-tuft, flowers, sand-ripples, pebbles and dry-grass decoratives
+    pub fn default_table() -> Self {
+        let mut table = Self::new();
+        table
+            .add(Biome::new("grassland", 0.0, 0.25, 0.0, &[("grass", 3), ("grass1", 2)])
+                .with_trees(0.12)
+                .with_rocks(0.0005)
+                .with_decoratives(&[("grass-tuft", 0.06), ("flowers", 0.015)]))
+            .add(Biome::new("desert", 0.35, -0.3, 0.0, &[("sand", 6), ("dirt", 1)])
+                .with_trees(0.003)
+                .with_rocks(0.003)
+                .with_decoratives(&[("sand-ripples", 0.04), ("pebbles", 0.02)]))
+            .add(Biome::new("dirt", 0.1, -0.1, 0.3, &[("dirt", 4), ("grass1", 1)])
+                .with_trees(0.03)
+                .with_rocks(0.002)
+                .with_decoratives(&[("pebbles", 0.04), ("dry-grass", 0.03)]));
+        table
+    }
+}
+
+///a BiomeTable with its tiles looked up
+#[derive(Debug, Clone)]
+pub struct ResolvedBiomes {
+    table: BiomeTable,
+    choices: Vec<Vec<(TileId, u32)>>,
+}
+
+impl ResolvedBiomes {
+    pub fn table(&self) -> &BiomeTable {
+        &self.table
+    }
+
+    ///the tile at (x, y) for the given climate. which of the biome's tiles it is only
+    ///depends on seed and the position
+    pub fn tile(&self, seed: u64, x: i32, y: i32, temperature: f64, moisture: f64, elevation: f64) -> Option<TileId> {
+        let tiles = &self.choices[self.table.select_index(temperature, moisture, elevation)?];
+
+        let total: u64 = tiles.iter().map(|(_, w)| *w as u64).sum();
+        let mut roll = noise::sub_seed(noise::sub_seed(seed, x as u64), y as u64) % total;
+        for (tile, weight) in tiles {
+            if roll < *weight as u64 {
+                return Some(*tile);
+            }
+            roll -= *weight as u64;
+        }
+        None
+    }
+}
@@ -0,0 +1,84 @@
+use std::{collections::HashMap, io::{self, Read, Write}};
+
+use super::{area::ChunkArea, ChunkCoord};
+
+const MAGIC: &[u8; 4] = b"FGMP";
+const VERSION: u8 = 1;
+
+///the chunks of one surface a force has seen, and the tick each was last seen on.
+///everything else is covered by fog of war
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chart {
+    seen: HashMap<ChunkCoord, u64>,
+}
+
+impl Chart {
+    pub fn new() -> Self {
+        Self { seen: HashMap::new() }
+    }
+
+    ///marks coord as seen on tick. returns whether it wasn't charted before
+    pub fn chart(&mut self, coord: ChunkCoord, tick: u64) -> bool {
+        self.seen.insert(coord, tick).is_none()
+    }
+
+    ///charts every chunk in area and returns the ones that were new
+    pub fn chart_area(&mut self, area: ChunkArea, tick: u64) -> Vec<ChunkCoord> {
+        area.iter().filter(|c| self.chart(*c, tick)).collect()
+    }
+
+    pub fn is_charted(&self, coord: ChunkCoord) -> bool {
+        self.seen.contains_key(&coord)
+    }
+
+    #[allow(dead_code)]
+    pub fn last_seen(&self, coord: ChunkCoord) -> Option<u64> {
+        self.seen.get(&coord).copied()
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut seen: Vec<(&ChunkCoord, &u64)> = self.seen.iter().collect();
+        seen.sort_by_key(|(c, _)| (c.x, c.y));
+
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+        w.write_all(&(seen.len() as u32).to_le_bytes())?;
+        for (coord, tick) in seen {
+            w.write_all(&coord.x.to_le_bytes())?;
+            w.write_all(&coord.y.to_le_bytes())?;
+            w.write_all(&tick.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(r: &mut R) -> io::Result<Chart> {
+        let mut header = [0; 5];
+        r.read_exact(&mut header)?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a chart"));
+        }
+
+        let mut chart = Chart::new();
+        for _ in 0..u32::from_le_bytes(read_array(r)?) {
+            let coord = ChunkCoord::new(i32::from_le_bytes(read_array(r)?), i32::from_le_bytes(read_array(r)?));
+            chart.seen.insert(coord, u64::from_le_bytes(read_array(r)?));
+        }
+        Ok(chart)
+    }
+}
+
+fn read_array<R: Read, const N: usize>(r: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
@@ -1,37 +1,166 @@
-use crate::world::tile::Tile;
+use std::collections::BTreeMap;
+
+use crate::world::tile::{DecorativeId, TileId};
 
 use super::{ChunkCoord, TileCoord};
 
 
 pub const CHUNK_SIZE: usize = 32;
 
+///the last generation stage a chunk has completed. stages run in this order
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum GenStatus {
+    #[default]
+    Tiles,
+    Resources,
+    Decoratives,
+    Entities,
+    Finished,
+}
+
+impl GenStatus {
+    pub const ALL: [GenStatus; 5] = [GenStatus::Tiles, GenStatus::Resources, GenStatus::Decoratives, GenStatus::Entities, GenStatus::Finished];
+
+    pub fn next(self) -> Option<GenStatus> {
+        Self::ALL.get(self as usize + 1).copied()
+    }
+
+    pub fn from_u8(value: u8) -> Option<GenStatus> {
+        Self::ALL.get(value as usize).copied()
+    }
+}
 
 
+///a purely visual detail on a tile, like a tuft of grass. these are only drawn, never simulated
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Decorative {
+    pub id: DecorativeId,
+    ///index into Chunk::tiles
+    pub cell: u16,
+}
+
+#[derive(Clone, Debug)]
 pub struct Chunk {
-    pub tiles: [Tile; CHUNK_SIZE*CHUNK_SIZE],
-    pub position: ChunkCoord
+    ///the ground layer, what the generator produced
+    pub tiles: [TileId; CHUNK_SIZE*CHUNK_SIZE],
+    ///tiles placed on top of the ground (floors, landfill), bottom to top, by tile index.
+    ///most cells have none so this is kept sparse
+    pub overlays: BTreeMap<u16, Vec<TileId>>,
+    pub decoratives: Vec<Decorative>,
+    pub position: ChunkCoord,
+    pub status: GenStatus,
+    ///set when tiles change so the renderer knows to redraw this chunk.
+    ///new chunks start dirty since they have never been drawn
+    pub dirty: bool,
 }
 
 impl Chunk {
-    pub fn new(position: ChunkCoord, tiles: [Tile; CHUNK_SIZE*CHUNK_SIZE]) -> Chunk {
+    pub fn new(position: ChunkCoord, tiles: [TileId; CHUNK_SIZE*CHUNK_SIZE]) -> Chunk {
 
         Chunk {
             tiles,
-            position
+            overlays: BTreeMap::new(),
+            decoratives: vec![],
+            position,
+            status: GenStatus::Tiles,
+            dirty: true,
         }
     }
 
-    pub fn update(&mut self) {
-        
+    ///ticks the chunk. returns whether it still has work to do next tick
+    pub fn update(&mut self) -> bool {
+        false
     }
-    #[allow(dead_code)]
-    pub fn get_tile(&self, coord: TileCoord) -> Result<Tile, ()> {
+
+    ///index into tiles for a coordinate relative to the chunk
+    fn index(coord: TileCoord) -> Result<usize, ()> {
         if coord.x < 0 || coord.x > (CHUNK_SIZE-1) as i32 ||
             coord.y < 0 || coord.y > (CHUNK_SIZE-1) as i32 {
                 return Err(());
         }
-        else {
-            return Ok(self.tiles[(coord.x * CHUNK_SIZE as i32 + coord.y) as usize].clone());
+        Ok((coord.x * CHUNK_SIZE as i32 + coord.y) as usize)
+    }
+
+    ///the visible tile at an index into tiles: the top overlay, or the ground if there is none
+    pub fn top_tile(&self, index: usize) -> TileId {
+        match self.overlays.get(&(index as u16)).and_then(|layers| layers.last()) {
+            Some(tile) => *tile,
+            None => self.tiles[index],
         }
     }
-}
\ No newline at end of file
+
+    ///the visible tile at coord (relative to the chunk)
+    pub fn get_tile(&self, coord: TileCoord) -> Result<TileId, ()> {
+        Ok(self.top_tile(Self::index(coord)?))
+    }
+
+    ///the tile under the visible one, None if the visible tile is the ground
+    pub fn get_hidden_tile(&self, coord: TileCoord) -> Result<Option<TileId>, ()> {
+        let index = Self::index(coord)?;
+        let Some(layers) = self.overlays.get(&(index as u16)) else { return Ok(None); };
+        let hidden = if layers.len() >= 2 { layers[layers.len() - 2] } else { self.tiles[index] };
+        Ok(Some(hidden))
+    }
+
+    ///replaces the visible tile at coord (relative to the chunk), marking the chunk dirty.
+    ///returns whether the tile actually changed
+    pub fn set_tile(&mut self, coord: TileCoord, tile: TileId) -> Result<bool, ()> {
+        let index = Self::index(coord)?;
+
+        let slot = match self.overlays.get_mut(&(index as u16)).and_then(|layers| layers.last_mut()) {
+            Some(top) => top,
+            None => &mut self.tiles[index],
+        };
+        if *slot == tile {
+            return Ok(false);
+        }
+        *slot = tile;
+        self.dirty = true;
+        Ok(true)
+    }
+
+    ///puts a tile on top of the current one, which is kept underneath. returns whether the
+    ///visible tile changed
+    pub fn place_tile(&mut self, coord: TileCoord, tile: TileId) -> Result<bool, ()> {
+        let index = Self::index(coord)?;
+        if self.top_tile(index) == tile {
+            return Ok(false);
+        }
+        self.overlays.entry(index as u16).or_default().push(tile);
+        self.dirty = true;
+        Ok(true)
+    }
+
+    ///removes the top layer, uncovering the tile below it. the ground layer can't be removed
+    pub fn remove_top_tile(&mut self, coord: TileCoord) -> Result<Option<TileId>, ()> {
+        let index = Self::index(coord)? as u16;
+        let Some(layers) = self.overlays.get_mut(&index) else { return Ok(None); };
+
+        let removed = layers.pop();
+        if layers.is_empty() {
+            self.overlays.remove(&index);
+        }
+        self.dirty = true;
+        Ok(removed)
+    }
+
+    pub fn add_decorative(&mut self, coord: TileCoord, id: DecorativeId) -> Result<(), ()> {
+        let cell = Self::index(coord)? as u16;
+        self.decoratives.push(Decorative { id, cell });
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn decoratives_at(&self, coord: TileCoord) -> Vec<DecorativeId> {
+        let Ok(cell) = Self::index(coord) else { return vec![]; };
+        self.decoratives.iter().filter(|d| d.cell as usize == cell).map(|d| d.id).collect()
+    }
+
+    ///removes every decorative at coord and returns how many there were
+    pub fn remove_decoratives(&mut self, coord: TileCoord) -> usize {
+        let Ok(cell) = Self::index(coord) else { return 0; };
+        let before = self.decoratives.len();
+        self.decoratives.retain(|d| d.cell as usize != cell);
+        before - self.decoratives.len()
+    }
+}
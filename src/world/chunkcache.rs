@@ -0,0 +1,268 @@
+use std::{collections::HashMap, fs, io::{self, Read, Write}, path::PathBuf, sync::atomic::{AtomicU32, Ordering}};
+
+use super::{chunk::{Chunk, Decorative, GenStatus, CHUNK_SIZE}, tile::{DecorativeId, TileId, TileManager}, ChunkCoord};
+
+const MAGIC: &[u8; 4] = b"FGCH";
+const VERSION: u8 = 4;
+
+///caches created by this process so far, so every cache gets a directory of its own
+static CACHES: AtomicU32 = AtomicU32::new(0);
+
+///where this process keeps its chunk caches. the pid keeps a game and a tool running at the
+///same time out of each other's caches
+fn session_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("factorygame-{}", std::process::id()))
+}
+
+///on-disk store for chunks that were unloaded from a surface.
+///tiles and decoratives are written by name rather than id so a reloaded chunk comes back exactly as it was evicted
+pub struct ChunkCache {
+    dir: PathBuf,
+    ///the chunks in the cache and the stage they had reached
+    stored: HashMap<ChunkCoord, GenStatus>,
+}
+
+impl ChunkCache {
+    ///creates an empty cache for the named surface in a new directory, which is deleted again
+    ///when the cache is dropped. the name must be a valid surface name, see is_valid_surface_name
+    pub fn for_surface(name: &str) -> ChunkCache {
+        assert!(super::is_valid_surface_name(name), "invalid surface name {name:?}");
+        let n = CACHES.fetch_add(1, Ordering::Relaxed);
+        ChunkCache { dir: session_dir().join(format!("{name}-{n}")), stored: HashMap::new() }
+    }
+
+    pub fn contains(&self, coord: ChunkCoord) -> bool {
+        self.stored.contains_key(&coord)
+    }
+
+    ///drops a chunk from the cache, returning the stage it had reached
+    pub fn forget(&mut self, coord: ChunkCoord) -> Option<GenStatus> {
+        let status = self.stored.remove(&coord)?;
+        let _ = fs::remove_file(self.path(coord));
+        Some(status)
+    }
+
+    pub(super) fn path(&self, coord: ChunkCoord) -> PathBuf {
+        self.dir.join(format!("{}_{}.chunk", coord.x, coord.y))
+    }
+
+    pub fn store(&mut self, chunk: &Chunk, tileman: &TileManager) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let mut palette: Vec<TileId> = vec![];
+        let mut palette_index = |tile: TileId| -> u16 {
+            match palette.iter().position(|id| *id == tile) {
+                Some(idx) => idx as u16,
+                None => {
+                    palette.push(tile);
+                    (palette.len() - 1) as u16
+                }
+            }
+        };
+        let indices: Vec<u16> = chunk.tiles.iter().map(|t| palette_index(*t)).collect();
+        let overlays: Vec<(u16, Vec<u16>)> = chunk.overlays.iter()
+            .map(|(cell, layers)| (*cell, layers.iter().map(|t| palette_index(*t)).collect()))
+            .collect();
+
+        let mut bytes = vec![];
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&chunk.position.x.to_le_bytes());
+        bytes.extend_from_slice(&chunk.position.y.to_le_bytes());
+        bytes.push(chunk.status as u8);
+        bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+        for id in palette {
+            let name = tileman.name(id).ok_or_else(|| invalid("unknown tile"))?;
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+        }
+        for idx in indices {
+            bytes.extend_from_slice(&idx.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(overlays.len() as u16).to_le_bytes());
+        for (cell, layers) in overlays {
+            bytes.extend_from_slice(&cell.to_le_bytes());
+            bytes.push(layers.len() as u8);
+            for idx in layers {
+                bytes.extend_from_slice(&idx.to_le_bytes());
+            }
+        }
+
+        let mut kinds: Vec<DecorativeId> = vec![];
+        for decorative in &chunk.decoratives {
+            if !kinds.contains(&decorative.id) {
+                kinds.push(decorative.id);
+            }
+        }
+        bytes.extend_from_slice(&(kinds.len() as u16).to_le_bytes());
+        for id in &kinds {
+            let name = tileman.decorative_name(*id).ok_or_else(|| invalid("unknown decorative"))?;
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+        }
+        bytes.extend_from_slice(&(chunk.decoratives.len() as u16).to_le_bytes());
+        for decorative in &chunk.decoratives {
+            let kind = kinds.iter().position(|id| *id == decorative.id).unwrap() as u16;
+            bytes.extend_from_slice(&decorative.cell.to_le_bytes());
+            bytes.extend_from_slice(&kind.to_le_bytes());
+        }
+
+        fs::File::create(self.path(chunk.position))?.write_all(&bytes)?;
+        self.stored.insert(chunk.position, chunk.status);
+        Ok(())
+    }
+
+    pub fn load(&self, coord: ChunkCoord, tileman: &TileManager) -> io::Result<Chunk> {
+        let mut bytes = vec![];
+        fs::File::open(self.path(coord))?.read_to_end(&mut bytes)?;
+        let mut reader = ByteReader { bytes: &bytes, pos: 0 };
+
+        if reader.take(4)? != MAGIC || reader.u8()? != VERSION {
+            return Err(invalid("not a chunk file"));
+        }
+        let position = ChunkCoord::new(reader.i32()?, reader.i32()?);
+        if position != coord {
+            return Err(invalid("chunk position mismatch"));
+        }
+        let status = GenStatus::from_u8(reader.u8()?).ok_or_else(|| invalid("bad generation status"))?;
+
+        let mut palette = vec![];
+        for _ in 0..reader.u16()? {
+            let len = reader.u16()? as usize;
+            let name = std::str::from_utf8(reader.take(len)?).map_err(|_| invalid("bad tile name"))?;
+            let id = tileman.get_id(name).map_err(|_| invalid("unknown tile"))?;
+            palette.push(id);
+        }
+
+        let mut tiles = [TileId::default(); CHUNK_SIZE*CHUNK_SIZE];
+        for tile in tiles.iter_mut() {
+            *tile = *palette.get(reader.u16()? as usize).ok_or_else(|| invalid("bad palette index"))?;
+        }
+
+        let mut chunk = Chunk::new(position, tiles);
+        for _ in 0..reader.u16()? {
+            let cell = reader.u16()?;
+            let mut layers = vec![];
+            for _ in 0..reader.u8()? {
+                layers.push(*palette.get(reader.u16()? as usize).ok_or_else(|| invalid("bad palette index"))?);
+            }
+            chunk.overlays.insert(cell, layers);
+        }
+
+        let mut kinds = vec![];
+        for _ in 0..reader.u16()? {
+            let len = reader.u16()? as usize;
+            let name = std::str::from_utf8(reader.take(len)?).map_err(|_| invalid("bad decorative name"))?;
+            kinds.push(tileman.decorative_id(name).map_err(|_| invalid("unknown decorative"))?);
+        }
+        for _ in 0..reader.u16()? {
+            let cell = reader.u16()?;
+            let id = *kinds.get(reader.u16()? as usize).ok_or_else(|| invalid("bad decorative index"))?;
+            chunk.decoratives.push(Decorative { id, cell });
+        }
+        chunk.status = status;
+        Ok(chunk)
+    }
+}
+
+impl Drop for ChunkCache {
+    fn drop(&mut self) {
+        if self.dir.exists() {
+            if let Err(e) = fs::remove_dir_all(&self.dir) {
+                println!("Could not remove chunk cache {}: {e}", self.dir.display());
+            }
+        }
+        //only succeeds once the last cache of the session is gone
+        let _ = fs::remove_dir(session_dir());
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self.pos + len;
+        if end > self.bytes.len() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{is_valid_surface_name, test_tiles, TileCoord};
+
+    fn chunk(tileman: &TileManager) -> Chunk {
+        Chunk::new(ChunkCoord::new(3, -2), [tileman.get_id("grass").unwrap(); CHUNK_SIZE*CHUNK_SIZE])
+    }
+
+    #[test]
+    fn caches_get_their_own_directory() {
+        let tileman = test_tiles();
+        let mut a = ChunkCache::for_surface("main");
+        let mut b = ChunkCache::for_surface("main");
+        a.store(&chunk(&tileman), &tileman).unwrap();
+        b.store(&chunk(&tileman), &tileman).unwrap();
+        assert_ne!(a.dir, b.dir);
+        assert!(a.dir.starts_with(session_dir()));
+
+        let dir = a.dir.clone();
+        drop(a);
+        assert!(!dir.exists());
+        assert!(b.load(ChunkCoord::new(3, -2), &tileman).is_ok());
+
+        for name in ["", "..", "../main", "a/b", "a\\b"] {
+            assert!(!is_valid_surface_name(name), "{name}");
+        }
+        assert!(is_valid_surface_name("nauvis-orbit.2"));
+    }
+
+    #[test]
+    fn store_load_round_trip() {
+        let tileman = test_tiles();
+        let id = |name| tileman.get_id(name).unwrap();
+        let mut chunk = chunk(&tileman);
+        chunk.tiles[5] = id("sand");
+        chunk.place_tile(TileCoord::new(1, 2), id("dirt")).unwrap();
+        chunk.place_tile(TileCoord::new(1, 2), id("shore")).unwrap();
+        chunk.place_tile(TileCoord::new(31, 31), id("water")).unwrap();
+        chunk.add_decorative(TileCoord::new(4, 4), tileman.decorative_id("flowers").unwrap()).unwrap();
+        chunk.add_decorative(TileCoord::new(0, 9), tileman.decorative_id("pebbles").unwrap()).unwrap();
+        chunk.status = GenStatus::Entities;
+
+        let mut cache = ChunkCache::for_surface("round-trip");
+        cache.store(&chunk, &tileman).unwrap();
+        let loaded = cache.load(chunk.position, &tileman).unwrap();
+
+        assert_eq!(loaded.position, chunk.position);
+        assert_eq!(loaded.status, GenStatus::Entities);
+        assert!(loaded.tiles == chunk.tiles);
+        assert_eq!(loaded.overlays, chunk.overlays);
+        assert_eq!(loaded.overlays[&(CHUNK_SIZE as u16 + 2)], vec![id("dirt"), id("shore")]);
+        assert_eq!(loaded.decoratives, chunk.decoratives);
+
+        //a chunk stored somewhere else doesn't load in its place
+        assert!(cache.load(ChunkCoord::new(0, 0), &tileman).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use super::{area::{BoundingBox, TileArea}, Coordinate, TileCoord};
+
+///compass direction in 45 degree steps. y grows downwards, so north is -y
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum Direction {
+    #[default]
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl Direction {
+    #[allow(dead_code)]
+    pub const CARDINAL: [Direction; 4] = [Direction::North, Direction::East, Direction::South, Direction::West];
+    pub const ALL: [Direction; 8] = [
+        Direction::North, Direction::NorthEast, Direction::East, Direction::SouthEast,
+        Direction::South, Direction::SouthWest, Direction::West, Direction::NorthWest,
+    ];
+
+    ///number of 45 degree steps clockwise from north
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_index(index: i32) -> Self {
+        Self::ALL[index.rem_euclid(8) as usize]
+    }
+
+    #[allow(dead_code)]
+    pub fn is_cardinal(self) -> bool {
+        self.index().is_multiple_of(2)
+    }
+
+    #[allow(dead_code)]
+    pub fn is_diagonal(self) -> bool {
+        !self.is_cardinal()
+    }
+
+    ///rotates by steps of 45 degrees, positive is clockwise
+    pub fn rotate(self, steps: i32) -> Self {
+        Self::from_index(self.index() as i32 + steps)
+    }
+
+    ///90 degrees clockwise
+    #[allow(dead_code)]
+    pub fn rotate_cw(self) -> Self {
+        self.rotate(2)
+    }
+
+    ///90 degrees counter clockwise
+    #[allow(dead_code)]
+    pub fn rotate_ccw(self) -> Self {
+        self.rotate(-2)
+    }
+
+    pub fn opposite(self) -> Self {
+        self.rotate(4)
+    }
+
+    ///unit step in this direction
+    pub fn offset(self) -> TileCoord {
+        match self {
+            Direction::North => TileCoord::new(0, -1),
+            Direction::NorthEast => TileCoord::new(1, -1),
+            Direction::East => TileCoord::new(1, 0),
+            Direction::SouthEast => TileCoord::new(1, 1),
+            Direction::South => TileCoord::new(0, 1),
+            Direction::SouthWest => TileCoord::new(-1, 1),
+            Direction::West => TileCoord::new(-1, 0),
+            Direction::NorthWest => TileCoord::new(-1, -1),
+        }
+    }
+
+    ///the direction pointing along offset, None for (0, 0). only the sign of each axis is used
+    #[allow(dead_code)]
+    pub fn from_offset(offset: TileCoord) -> Option<Self> {
+        let step = TileCoord::new(offset.x.signum(), offset.y.signum());
+        Self::ALL.into_iter().find(|d| d.offset() == step)
+    }
+
+    ///number of clockwise quarter turns from north. diagonals round down to the previous cardinal
+    pub fn quarter_turns(self) -> i32 {
+        self.index() as i32 / 2
+    }
+}
+
+
+impl TileCoord {
+    pub fn offset(self, dx: i32, dy: i32) -> Self {
+        TileCoord::new(self.x + dx, self.y + dy)
+    }
+
+    ///the tile distance steps away in direction dir
+    pub fn step(self, dir: Direction, distance: i32) -> Self {
+        let o = dir.offset();
+        self.offset(o.x * distance, o.y * distance)
+    }
+
+    pub fn neighbor(self, dir: Direction) -> Self {
+        self.step(dir, 1)
+    }
+
+    ///the four tiles sharing an edge with this one, starting north and going clockwise
+    #[allow(dead_code)]
+    pub fn neighbors4(self) -> [TileCoord; 4] {
+        Direction::CARDINAL.map(|d| self.neighbor(d))
+    }
+
+    ///all eight surrounding tiles, starting north and going clockwise
+    pub fn neighbors8(self) -> [TileCoord; 8] {
+        Direction::ALL.map(|d| self.neighbor(d))
+    }
+
+    ///rotates a position relative to an entity's origin tile so that what faced north faces dir.
+    ///diagonal directions round down to the previous cardinal
+    pub fn rotate(self, dir: Direction) -> Self {
+        let mut c = self;
+        for _ in 0..dir.quarter_turns() {
+            c = TileCoord::new(-c.y, c.x);
+        }
+        c
+    }
+
+    ///mirrors a relative position along the x axis
+    #[allow(dead_code)]
+    pub fn flip_horizontal(self) -> Self {
+        TileCoord::new(-self.x, self.y)
+    }
+
+    ///mirrors a relative position along the y axis
+    #[allow(dead_code)]
+    pub fn flip_vertical(self) -> Self {
+        TileCoord::new(self.x, -self.y)
+    }
+}
+
+impl Coordinate {
+    ///rotates around the origin so that what faced north faces dir
+    #[allow(dead_code)]
+    pub fn rotate(self, dir: Direction) -> Self {
+        let mut c = self;
+        for _ in 0..dir.quarter_turns() {
+            c = Coordinate::new(-c.y, c.x);
+        }
+        c
+    }
+}
+
+impl TileArea {
+    ///rotates an area of positions relative to an entity's origin tile, see TileCoord::rotate
+    pub fn rotate(&self, dir: Direction) -> Self {
+        if self.is_empty() { return *self; }
+        TileArea::new(self.min.rotate(dir), self.max.rotate(dir))
+    }
+
+    #[allow(dead_code)]
+    pub fn flip_horizontal(&self) -> Self {
+        if self.is_empty() { return *self; }
+        TileArea::new(self.min.flip_horizontal(), self.max.flip_horizontal())
+    }
+
+    #[allow(dead_code)]
+    pub fn flip_vertical(&self) -> Self {
+        if self.is_empty() { return *self; }
+        TileArea::new(self.min.flip_vertical(), self.max.flip_vertical())
+    }
+}
+
+impl BoundingBox {
+    ///rotates a box given relative to an entity's position around that position
+    #[allow(dead_code)]
+    pub fn rotate(&self, dir: Direction) -> Self {
+        BoundingBox::new(self.min.rotate(dir), self.max.rotate(dir))
+    }
+
+    #[allow(dead_code)]
+    pub fn flip_horizontal(&self) -> Self {
+        BoundingBox::new(Coordinate::new(-self.min.x, self.min.y), Coordinate::new(-self.max.x, self.max.y))
+    }
+
+    #[allow(dead_code)]
+    pub fn flip_vertical(&self) -> Self {
+        BoundingBox::new(Coordinate::new(self.min.x, -self.min.y), Coordinate::new(self.max.x, -self.max.y))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotating_directions() {
+        assert_eq!(Direction::North.rotate_cw(), Direction::East);
+        assert_eq!(Direction::North.rotate_ccw(), Direction::West);
+        assert_eq!(Direction::NorthWest.rotate(1), Direction::North);
+        assert_eq!(Direction::SouthEast.opposite(), Direction::NorthWest);
+        assert_eq!(Direction::from_index(-1), Direction::NorthWest);
+        assert_eq!(Direction::East.rotate(-10), Direction::North);
+        for dir in Direction::ALL {
+            assert_eq!(dir.rotate(8), dir);
+            assert_eq!(Direction::from_offset(dir.offset()), Some(dir));
+            assert_eq!(dir.is_cardinal(), Direction::CARDINAL.contains(&dir));
+        }
+        assert_eq!(Direction::from_offset(TileCoord::new(5, -2)), Some(Direction::NorthEast));
+        assert_eq!(Direction::from_offset(TileCoord::new(0, 0)), None);
+    }
+
+    #[test]
+    fn rotating_positions() {
+        //what faces north ends up facing dir
+        let front = Direction::North.offset();
+        for dir in Direction::CARDINAL {
+            assert_eq!(front.rotate(dir), dir.offset());
+        }
+        assert_eq!(front.rotate(Direction::SouthWest), Direction::South.offset());
+        assert_eq!(Coordinate::new(0.5, -1.0).rotate(Direction::East), Coordinate::new(1.0, 0.5));
+
+        //a 2x3 area turns into a 3x2 one
+        let area = TileArea::new(TileCoord::new(0, -2), TileCoord::new(1, 0));
+        let east = area.rotate(Direction::East);
+        assert_eq!(east, TileArea::new(TileCoord::new(0, 0), TileCoord::new(2, 1)));
+        assert_eq!((east.width(), east.height()), (3, 2));
+        assert_eq!(area.rotate(Direction::North), area);
+        assert!(TileArea::empty().rotate(Direction::South).is_empty());
+
+        let bbox = BoundingBox::new(Coordinate::new(-0.5, -1.5), Coordinate::new(0.5, 0.5));
+        assert_eq!(bbox.rotate(Direction::East), BoundingBox::new(Coordinate::new(-0.5, -0.5), Coordinate::new(1.5, 0.5)));
+    }
+
+    #[test]
+    fn flipping() {
+        let tile = TileCoord::new(2, -3);
+        assert_eq!(tile.flip_horizontal(), TileCoord::new(-2, -3));
+        assert_eq!(tile.flip_vertical(), TileCoord::new(2, 3));
+        assert_eq!(tile.flip_horizontal().flip_horizontal(), tile);
+
+        let area = TileArea::new(TileCoord::new(0, -2), TileCoord::new(1, 0));
+        assert_eq!(area.flip_horizontal(), TileArea::new(TileCoord::new(-1, -2), TileCoord::new(0, 0)));
+        assert_eq!(area.flip_vertical(), TileArea::new(TileCoord::new(0, 0), TileCoord::new(1, 2)));
+
+        let bbox = BoundingBox::new(Coordinate::new(0.0, -1.0), Coordinate::new(2.0, 0.5));
+        assert_eq!(bbox.flip_horizontal(), BoundingBox::new(Coordinate::new(-2.0, -1.0), Coordinate::new(0.0, 0.5)));
+        assert_eq!(bbox.flip_vertical(), BoundingBox::new(Coordinate::new(0.0, -0.5), Coordinate::new(2.0, 1.0)));
+    }
+}
@@ -1,3 +1,5 @@
+use super::{area::TileArea, direction::Direction, force::ForceId, TileCoord};
+
 #[derive(Debug, Copy, Clone, Default)]
 struct CraftingBehavior;
 
@@ -6,14 +8,37 @@ struct CraftingBehavior;
 struct BeltBehavior;
 
 
+///charts the chunks around the entity for its force
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RadarBehavior {
+    ///in chunks, around the chunk the radar stands in
+    pub radius: i32,
+}
+
+
 #[derive(Debug, Copy, Clone, Default)]
 pub enum Behavior {
     #[default] None,
     CraftingMachine(CraftingBehavior),
     TransportBelt(BeltBehavior),
+    #[allow(dead_code)]
+    Radar(RadarBehavior),
 
 }
 
+///what an entity turns into when it is mined, e.g. a tree gives wood
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Minable {
+    pub result: String,
+    pub count: u32,
+}
+
+impl Minable {
+    pub fn new(result: &str, count: u32) -> Self {
+        Self { result: result.to_owned(), count }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Default)]
 pub struct Inventory;
 
@@ -23,12 +48,86 @@ pub struct Blueprint;
 
 
 
-#[derive(Debug, Copy, Clone, Default)]
-pub struct EID (u32);
-#[derive(Debug, Copy, Clone, Default)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct EID (pub u32);
+#[derive(Debug, Clone)]
 pub struct Entity {
-    _id: EID,
+    id: EID,
+    ///prototype name, e.g. "iron-chest"
+    pub name: String,
+    ///the entity's origin tile
+    pub position: TileCoord,
+    pub direction: Direction,
+    ///tiles covered relative to position, when facing north
+    pub footprint: TileArea,
+    ///the force that owns this entity. neutral unless set
+    pub force: ForceId,
+    ///blocks walking and building on the tiles it covers
+    pub solid: bool,
+    ///what is left to mine, for resource entities
+    pub resource_amount: Option<u32>,
+    ///set for entities that can be mined as a whole, like trees and rocks
+    pub minable: Option<Minable>,
     pub inventory: Inventory,
     pub behavior: Behavior,
     pub bp: Blueprint,
+}
+
+impl Default for Entity {
+    fn default() -> Self {
+        Self {
+            id: EID::default(),
+            name: String::new(),
+            position: TileCoord::default(),
+            direction: Direction::default(),
+            footprint: TileArea::around(TileCoord::new(0, 0), 0),
+            force: ForceId::NEUTRAL,
+            solid: false,
+            resource_amount: None,
+            minable: None,
+            inventory: Inventory,
+            behavior: Behavior::None,
+            bp: Blueprint,
+        }
+    }
+}
+
+impl Entity {
+    ///a single tile entity. it gets its id when it is placed on a surface
+    #[allow(dead_code)]
+    pub fn new(name: &str, position: TileCoord) -> Self {
+        Self { name: name.to_owned(), position, ..Default::default() }
+    }
+
+    #[allow(dead_code)]
+    pub fn id(&self) -> EID {
+        self.id
+    }
+
+    pub(super) fn set_id(&mut self, id: EID) {
+        self.id = id;
+    }
+
+    ///the tiles this entity covers on the surface
+    pub fn area(&self) -> TileArea {
+        self.footprint.rotate(self.direction).translate(self.position)
+    }
+
+    ///takes up to amount from a resource entity and returns how much was taken
+    pub fn mine(&mut self, amount: u32) -> u32 {
+        let Some(left) = self.resource_amount.as_mut() else { return 0; };
+        let taken = amount.min(*left);
+        *left -= taken;
+        taken
+    }
+
+    ///a resource entity with nothing left
+    pub fn is_depleted(&self) -> bool {
+        self.resource_amount == Some(0)
+    }
+
+    ///whether this entity does anything when ticked
+    pub fn is_active(&self) -> bool {
+        !matches!(self.behavior, Behavior::None)
+    }
 }
\ No newline at end of file
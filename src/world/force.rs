@@ -0,0 +1,380 @@
+use std::{collections::{HashMap, HashSet}, fs, io::{self, Read, Write}, path::Path};
+
+use super::{area::ChunkArea, chart::Chart, ChunkCoord};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ForceId(pub u8);
+
+impl ForceId {
+    ///owns resources, trees and anything else nobody has claimed
+    pub const NEUTRAL: ForceId = ForceId(0);
+    pub const PLAYER: ForceId = ForceId(1);
+    pub const ENEMY: ForceId = ForceId(2);
+}
+
+///how one force treats another. relations are one way: a force can be friends with another
+///that still considers it an enemy
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Relation {
+    Enemy,
+    ///won't attack, but doesn't share anything either
+    CeaseFire,
+    #[allow(dead_code)]
+    Friend,
+}
+
+///modifiers unlocked through research
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Bonuses {
+    pub crafting_speed: f32,
+    pub mining_speed: f32,
+    pub mining_productivity: f32,
+    pub inventory_slots: u32,
+}
+
+impl Default for Bonuses {
+    fn default() -> Self {
+        Self {
+            crafting_speed: 1.0,
+            mining_speed: 1.0,
+            mining_productivity: 0.0,
+            inventory_slots: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Force {
+    #[allow(dead_code)]
+    id: ForceId,
+    pub name: String,
+    #[allow(dead_code)]
+    pub researched: HashSet<String>,
+    #[allow(dead_code)]
+    pub recipes: HashSet<String>,
+    #[allow(dead_code)]
+    pub bonuses: Bonuses,
+    ///what this force has seen, by surface name
+    charts: HashMap<String, Chart>,
+    relations: HashMap<ForceId, Relation>,
+    ///relation to forces without an explicit entry
+    #[allow(dead_code)]
+    default_relation: Relation,
+}
+
+impl Force {
+    fn new(id: ForceId, name: &str, default_relation: Relation) -> Self {
+        Self {
+            id,
+            name: name.to_owned(),
+            researched: HashSet::new(),
+            recipes: HashSet::new(),
+            bonuses: Bonuses::default(),
+            charts: HashMap::new(),
+            relations: HashMap::new(),
+            default_relation,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn id(&self) -> ForceId {
+        self.id
+    }
+
+    #[allow(dead_code)]
+    pub fn unlock_recipe(&mut self, recipe: &str) {
+        self.recipes.insert(recipe.to_owned());
+    }
+
+    #[allow(dead_code)]
+    pub fn is_recipe_unlocked(&self, recipe: &str) -> bool {
+        self.recipes.contains(recipe)
+    }
+
+    ///marks a technology as researched and unlocks the recipes it gives
+    #[allow(dead_code)]
+    pub fn research(&mut self, technology: &str, recipes: &[&str]) {
+        self.researched.insert(technology.to_owned());
+        for recipe in recipes {
+            self.unlock_recipe(recipe);
+        }
+    }
+
+    ///marks chunk as seen on tick. returns whether it wasn't charted before
+    #[allow(dead_code)]
+    pub fn chart(&mut self, surface: &str, chunk: ChunkCoord, tick: u64) -> bool {
+        self.charts.entry(surface.to_owned()).or_default().chart(chunk, tick)
+    }
+
+    ///charts every chunk in area and returns the ones that were new
+    pub fn chart_area(&mut self, surface: &str, area: ChunkArea, tick: u64) -> Vec<ChunkCoord> {
+        self.charts.entry(surface.to_owned()).or_default().chart_area(area, tick)
+    }
+
+    #[allow(dead_code)]
+    pub fn is_charted(&self, surface: &str, chunk: ChunkCoord) -> bool {
+        self.charts.get(surface).is_some_and(|c| c.is_charted(chunk))
+    }
+
+    ///the force's chart of a surface, if it has seen any of it
+    pub fn chart_of(&self, surface: &str) -> Option<&Chart> {
+        self.charts.get(surface)
+    }
+
+    ///drops the chart of a surface, e.g. when the surface is deleted
+    pub fn forget_surface(&mut self, surface: &str) {
+        self.charts.remove(surface);
+    }
+
+    ///how this force treats other. a force is always its own friend
+    #[allow(dead_code)]
+    pub fn relation(&self, other: ForceId) -> Relation {
+        if other == self.id {
+            return Relation::Friend;
+        }
+        self.relations.get(&other).copied().unwrap_or(self.default_relation)
+    }
+}
+
+
+///every force in the game. the neutral, player and enemy forces always exist
+pub struct ForceManager {
+    forces: Vec<Force>,
+}
+
+impl ForceManager {
+    pub fn new() -> Self {
+        let mut forces = vec![
+            Force::new(ForceId::NEUTRAL, "neutral", Relation::CeaseFire),
+            Force::new(ForceId::PLAYER, "player", Relation::Enemy),
+            Force::new(ForceId::ENEMY, "enemy", Relation::Enemy),
+        ];
+        forces[ForceId::PLAYER.0 as usize].relations.insert(ForceId::NEUTRAL, Relation::CeaseFire);
+        forces[ForceId::ENEMY.0 as usize].relations.insert(ForceId::NEUTRAL, Relation::CeaseFire);
+        Self { forces }
+    }
+
+    ///adds a force, e.g. a second team for pvp. new forces are enemies of everyone but neutral
+    #[allow(dead_code)]
+    pub fn create(&mut self, name: &str) -> Result<ForceId, ()> {
+        if self.by_name(name).is_some() || self.forces.len() > u8::MAX as usize {
+            return Err(());
+        }
+        let id = ForceId(self.forces.len() as u8);
+        let mut force = Force::new(id, name, Relation::Enemy);
+        force.relations.insert(ForceId::NEUTRAL, Relation::CeaseFire);
+        self.forces.push(force);
+        Ok(id)
+    }
+
+    pub fn get(&self, id: ForceId) -> Option<&Force> {
+        self.forces.get(id.0 as usize)
+    }
+
+    pub fn get_mut(&mut self, id: ForceId) -> Option<&mut Force> {
+        self.forces.get_mut(id.0 as usize)
+    }
+
+    #[allow(dead_code)]
+    pub fn by_name(&self, name: &str) -> Option<&Force> {
+        self.forces.iter().find(|f| f.name == name)
+    }
+
+    #[allow(dead_code)]
+    pub fn iter(&self) -> impl Iterator<Item = &Force> {
+        self.forces.iter()
+    }
+
+    ///makes every force drop its chart of a surface
+    pub fn forget_surface(&mut self, surface: &str) {
+        for force in &mut self.forces {
+            force.forget_surface(surface);
+        }
+    }
+
+    ///sets how from treats to, without changing how to treats from
+    #[allow(dead_code)]
+    pub fn set_relation(&mut self, from: ForceId, to: ForceId, relation: Relation) -> Result<(), ()> {
+        if from == to || self.get(to).is_none() {
+            return Err(());
+        }
+        self.get_mut(from).ok_or(())?.relations.insert(to, relation);
+        Ok(())
+    }
+
+    ///makes two forces friends (or enemies again) in both directions, e.g. for co-op
+    #[allow(dead_code)]
+    pub fn set_friends(&mut self, a: ForceId, b: ForceId, friends: bool) -> Result<(), ()> {
+        let relation = if friends { Relation::Friend } else { Relation::Enemy };
+        self.set_relation(a, b, relation)?;
+        self.set_relation(b, a, relation)
+    }
+
+    #[allow(dead_code)]
+    pub fn relation(&self, from: ForceId, to: ForceId) -> Relation {
+        self.get(from).map_or(Relation::Enemy, |f| f.relation(to))
+    }
+
+    #[allow(dead_code)]
+    pub fn is_enemy(&self, from: ForceId, to: ForceId) -> bool {
+        self.relation(from, to) == Relation::Enemy
+    }
+
+    ///writes the charts of every force to dir, one file per force
+    pub fn save_charts<P: AsRef<Path>>(&self, dir: P) -> io::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        for force in &self.forces {
+            let mut charts: Vec<(&String, &Chart)> = force.charts.iter().collect();
+            charts.sort_by_key(|(name, _)| *name);
+
+            let mut bytes = vec![];
+            bytes.extend_from_slice(&(charts.len() as u16).to_le_bytes());
+            for (surface, chart) in charts {
+                bytes.extend_from_slice(&(surface.len() as u16).to_le_bytes());
+                bytes.extend_from_slice(surface.as_bytes());
+                chart.write(&mut bytes)?;
+            }
+            fs::File::create(dir.join(format!("{}.charts", force.name)))?.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+
+    ///replaces the charts of every force that has a file in dir. forces without one keep theirs
+    pub fn load_charts<P: AsRef<Path>>(&mut self, dir: P) -> io::Result<()> {
+        let dir = dir.as_ref();
+        for force in &mut self.forces {
+            let path = dir.join(format!("{}.charts", force.name));
+            if !path.exists() {
+                continue;
+            }
+            let mut file = io::BufReader::new(fs::File::open(path)?);
+
+            let mut charts = HashMap::new();
+            for _ in 0..read_u16(&mut file)? {
+                let mut name = vec![0; read_u16(&mut file)? as usize];
+                file.read_exact(&mut name)?;
+                let name = String::from_utf8(name).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad surface name"))?;
+                charts.insert(name, Chart::read(&mut file)?);
+            }
+            force.charts = charts;
+        }
+        Ok(())
+    }
+}
+
+fn read_u16<R: Read>(r: &mut R) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    r.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn friendship_goes_both_ways() {
+        let mut forces = ForceManager::new();
+        let blue = forces.create("blue").unwrap();
+        assert!(forces.create("blue").is_err());
+        assert_eq!(forces.by_name("blue").map(|f| f.id()), Some(blue));
+
+        //new forces start as enemies of everyone but neutral
+        assert!(forces.is_enemy(blue, ForceId::PLAYER) && forces.is_enemy(ForceId::PLAYER, blue));
+        assert_eq!(forces.relation(blue, ForceId::NEUTRAL), Relation::CeaseFire);
+        assert_eq!(forces.relation(blue, blue), Relation::Friend);
+
+        forces.set_friends(ForceId::PLAYER, blue, true).unwrap();
+        assert_eq!(forces.relation(ForceId::PLAYER, blue), Relation::Friend);
+        assert_eq!(forces.relation(blue, ForceId::PLAYER), Relation::Friend);
+        assert!(forces.is_enemy(blue, ForceId::ENEMY));
+
+        forces.set_friends(blue, ForceId::PLAYER, false).unwrap();
+        assert!(forces.is_enemy(ForceId::PLAYER, blue) && forces.is_enemy(blue, ForceId::PLAYER));
+
+        //set_relation only changes one side
+        forces.set_relation(blue, ForceId::PLAYER, Relation::CeaseFire).unwrap();
+        assert_eq!(forces.relation(blue, ForceId::PLAYER), Relation::CeaseFire);
+        assert!(forces.is_enemy(ForceId::PLAYER, blue));
+    }
+
+    #[test]
+    fn set_friends_needs_two_known_forces() {
+        let mut forces = ForceManager::new();
+        let unknown = ForceId(9);
+        assert!(forces.set_friends(ForceId::PLAYER, unknown, true).is_err());
+        assert!(forces.set_friends(unknown, ForceId::PLAYER, true).is_err());
+        assert!(forces.set_friends(ForceId::PLAYER, ForceId::PLAYER, true).is_err());
+        //a failed call leaves both sides as they were
+        assert!(forces.is_enemy(ForceId::PLAYER, unknown));
+        assert!(forces.is_enemy(unknown, ForceId::PLAYER));
+        assert!(!forces.get(ForceId::PLAYER).unwrap().relations.contains_key(&unknown));
+    }
+
+    #[test]
+    fn recipes_are_unlocked_per_force() {
+        let mut forces = ForceManager::new();
+        let blue = forces.create("blue").unwrap();
+        let player = forces.get_mut(ForceId::PLAYER).unwrap();
+        player.unlock_recipe("iron-gear");
+        player.research("automation", &["assembler", "inserter"]);
+
+        let player = forces.get(ForceId::PLAYER).unwrap();
+        for recipe in ["iron-gear", "assembler", "inserter"] {
+            assert!(player.is_recipe_unlocked(recipe), "{recipe}");
+        }
+        assert!(player.researched.contains("automation"));
+        assert!(!player.is_recipe_unlocked("radar"));
+
+        let blue = forces.get(blue).unwrap();
+        assert!(!blue.is_recipe_unlocked("assembler"));
+        assert!(blue.researched.is_empty());
+    }
+
+    #[test]
+    fn charts_round_trip() {
+        let mut forces = ForceManager::new();
+        let pvp = forces.create("pvp").unwrap();
+        let player = forces.get_mut(ForceId::PLAYER).unwrap();
+        player.chart_area("main", ChunkArea::around(ChunkCoord::new(0, 0), 2), 10);
+        player.chart("main", ChunkCoord::new(-40, 7), 99);
+        player.chart("orbit", ChunkCoord::new(1, 1), 5);
+        forces.get_mut(pvp).unwrap().chart("main", ChunkCoord::new(3, 3), 1);
+
+        let dir = std::env::temp_dir().join(format!("charts-test-{}", std::process::id()));
+        forces.save_charts(&dir).unwrap();
+
+        let mut loaded = ForceManager::new();
+        loaded.get_mut(ForceId::ENEMY).unwrap().chart("main", ChunkCoord::new(0, 0), 0);
+        let other = loaded.create("other").unwrap();
+        loaded.get_mut(other).unwrap().chart("main", ChunkCoord::new(0, 0), 0);
+        let result = loaded.load_charts(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        result.unwrap();
+
+        let player = loaded.get(ForceId::PLAYER).unwrap();
+        for surface in ["main", "orbit"] {
+            assert_eq!(player.chart_of(surface), forces.get(ForceId::PLAYER).unwrap().chart_of(surface));
+        }
+        assert_eq!(player.chart_of("main").unwrap().last_seen(ChunkCoord::new(-40, 7)), Some(99));
+        //a saved force without charts comes back without any
+        assert!(loaded.get(ForceId::ENEMY).unwrap().chart_of("main").is_none());
+        //forces that have no file keep what they had
+        assert!(loaded.get(other).unwrap().is_charted("main", ChunkCoord::new(0, 0)));
+    }
+
+    #[test]
+    fn chart_rejects_other_data() {
+        let mut chart = Chart::new();
+        chart.chart(ChunkCoord::new(2, -5), 3);
+        let mut bytes = vec![];
+        chart.write(&mut bytes).unwrap();
+        assert_eq!(Chart::read(&mut bytes.as_slice()).unwrap(), chart);
+
+        bytes[0] = b'X';
+        assert!(Chart::read(&mut bytes.as_slice()).is_err());
+        assert!(Chart::read(&mut &bytes[..7]).is_err());
+    }
+}
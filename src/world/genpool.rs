@@ -0,0 +1,199 @@
+use std::{
+    any::Any, collections::BTreeMap, panic::{self, AssertUnwindSafe}, sync::{mpsc, Arc, Mutex}, thread,
+};
+
+use super::{chunk::{Chunk, CHUNK_SIZE}, tile::{TileId, TileManager}, worldgen::Generator, ChunkCoord};
+
+struct Job {
+    seq: u64,
+    surface: String,
+    coord: ChunkCoord,
+    seed: u64,
+    generator: Arc<dyn Generator>,
+}
+
+///a chunk made by a worker, for the named surface
+pub struct Generated {
+    pub surface: String,
+    pub chunk: Chunk,
+    ///why the generator failed, if it did. the chunk is then filled with the first registered
+    ///tile, so the surface doesn't end up with a hole that never gets generated
+    pub failed: Option<String>,
+}
+
+///runs chunk generation on worker threads. results come back in the order the chunks were
+///submitted no matter which worker finishes first, so the world doesn't depend on thread timing
+pub struct GenPool {
+    jobs: Option<mpsc::Sender<Job>>,
+    results: mpsc::Receiver<(u64, Generated)>,
+    workers: Vec<thread::JoinHandle<()>>,
+    next_seq: u64,
+    ///sequence number of the next result to hand out
+    next_out: u64,
+    ///results that arrived before one submitted earlier
+    waiting: BTreeMap<u64, Generated>,
+}
+
+impl GenPool {
+    ///starts threads workers (at least one). tileman is shared with all of them
+    pub fn new(threads: usize, tileman: Arc<TileManager>) -> GenPool {
+        let (job_tx, job_rx) = mpsc::channel::<Job>();
+        let (result_tx, result_rx) = mpsc::channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let (jobs, results, tileman) = (job_rx.clone(), result_tx.clone(), tileman.clone());
+                thread::Builder::new()
+                    .name(format!("chunkgen-{i}"))
+                    .spawn(move || loop {
+                        let job = match jobs.lock() {
+                            Ok(rx) => rx.recv(),
+                            Err(_) => return,
+                        };
+                        let Ok(job) = job else { return; };
+                        let generated = panic::catch_unwind(AssertUnwindSafe(|| job.generator.gen_chunk(job.coord, job.seed, &tileman)));
+                        let (chunk, failed) = match generated {
+                            Ok(chunk) => (chunk, None),
+                            Err(e) => (Chunk::new(job.coord, [TileId::default(); CHUNK_SIZE*CHUNK_SIZE]), Some(panic_message(e))),
+                        };
+                        if results.send((job.seq, Generated { surface: job.surface, chunk, failed })).is_err() {
+                            return;
+                        }
+                    })
+                    .expect("could not start chunk generation thread")
+            })
+            .collect();
+
+        GenPool {
+            jobs: Some(job_tx),
+            results: result_rx,
+            workers,
+            next_seq: 0,
+            next_out: 0,
+            waiting: BTreeMap::new(),
+        }
+    }
+
+    ///one worker per core, leaving one for the game
+    pub fn with_default_threads(tileman: Arc<TileManager>) -> GenPool {
+        let cores = thread::available_parallelism().map_or(2, |n| n.get());
+        GenPool::new(cores.saturating_sub(1), tileman)
+    }
+
+    ///queues generation of a chunk for the named surface
+    pub fn submit(&mut self, surface: &str, coord: ChunkCoord, seed: u64, generator: Arc<dyn Generator>) {
+        let job = Job { seq: self.next_seq, surface: surface.to_owned(), coord, seed, generator };
+        self.next_seq += 1;
+        if let Some(jobs) = &self.jobs {
+            jobs.send(job).expect("chunk generation threads stopped");
+        }
+    }
+
+    ///chunks submitted but not handed out by poll yet
+    #[allow(dead_code)]
+    pub fn in_flight(&self) -> u64 {
+        self.next_seq - self.next_out
+    }
+
+    ///the finished chunks that are next in submission order. never blocks
+    pub fn poll(&mut self) -> Vec<Generated> {
+        while let Ok((seq, generated)) = self.results.try_recv() {
+            self.waiting.insert(seq, generated);
+        }
+
+        let mut ready = vec![];
+        while let Some(generated) = self.waiting.remove(&self.next_out) {
+            ready.push(generated);
+            self.next_out += 1;
+        }
+        ready
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(payload) => payload.downcast_ref::<&str>().map_or("unknown panic".to_owned(), |msg| msg.to_string()),
+    }
+}
+
+impl Drop for GenPool {
+    fn drop(&mut self) {
+        //closing the channel makes every worker return once it is idle
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{test_tiles, worldgen::NoiseGen};
+
+    #[test]
+    fn results_in_submission_order() {
+        let tileman = Arc::new(test_tiles());
+        let generator: Arc<dyn Generator> = Arc::new(NoiseGen::new(&tileman).unwrap());
+
+        let coords: Vec<ChunkCoord> = (-3..3).flat_map(|x| (-3..3).map(move |y| ChunkCoord::new(x, y))).collect();
+        let mut pool = GenPool::new(4, tileman.clone());
+        for coord in &coords {
+            pool.submit("main", *coord, 77, generator.clone());
+        }
+
+        let mut generated = vec![];
+        while generated.len() < coords.len() {
+            generated.extend(pool.poll());
+            thread::yield_now();
+        }
+        for (coord, g) in coords.iter().zip(generated) {
+            assert_eq!(g.chunk.position, *coord);
+            assert!(g.chunk.tiles == generator.gen_chunk(*coord, 77, &tileman).tiles);
+        }
+    }
+
+    ///panics on one chunk, and is slow on the chunks before it so the panic happens first
+    struct Faulty;
+
+    impl Generator for Faulty {
+        fn gen_chunk(&self, position: ChunkCoord, _seed: u64, tileman: &TileManager) -> Chunk {
+            if position.x < 3 {
+                thread::sleep(std::time::Duration::from_millis(20));
+            }
+            assert!(position.x != 3, "bad chunk");
+            Chunk::new(position, [tileman.get_id("sand").unwrap(); CHUNK_SIZE*CHUNK_SIZE])
+        }
+    }
+
+    #[test]
+    fn panicking_job_keeps_the_order() {
+        let tileman = Arc::new(test_tiles());
+        let sand = tileman.get_id("sand").unwrap();
+        let mut pool = GenPool::new(3, tileman);
+        for x in 0..8 {
+            pool.submit("main", ChunkCoord::new(x, 0), 1, Arc::new(Faulty));
+        }
+
+        let mut generated = vec![];
+        while generated.len() < 8 {
+            generated.extend(pool.poll());
+            thread::yield_now();
+        }
+        assert_eq!(pool.in_flight(), 0);
+        for (x, g) in generated.iter().enumerate() {
+            assert_eq!(g.chunk.position, ChunkCoord::new(x as i32, 0));
+            if x == 3 {
+                assert!(g.failed.as_deref().is_some_and(|e| e.contains("bad chunk")));
+                assert!(g.chunk.tiles.iter().all(|t| *t == TileId::default()));
+            }
+            else {
+                assert!(g.failed.is_none());
+                assert!(g.chunk.tiles.iter().all(|t| *t == sand));
+            }
+        }
+    }
+}
@@ -1,15 +1,33 @@
-use std::ops::{Add, Sub};
+use std::{collections::{HashMap, HashSet}, ops::{Add, Sub}, sync::Arc};
 
+pub mod area;
+pub mod biome;
+pub mod chart;
 pub mod chunk;
+pub mod chunkcache;
+pub mod direction;
 pub mod entity;
+pub mod force;
+pub mod genpool;
+pub mod noise;
+pub mod player;
+pub mod position;
+pub mod preview;
+pub mod resources;
+pub mod scriptgen;
+pub mod settings;
+pub mod spatial;
+pub mod surfaces;
+pub mod terrain;
 pub mod worldgen;
 pub mod tile;
+pub mod vegetation;
 
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub struct ChunkCoord { pub x: i32, pub y: i32 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub struct TileCoord { pub x: i32, pub y: i32 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Default)]
@@ -36,7 +54,7 @@ impl Coordinate {
 
 impl From<TileCoord> for ChunkCoord {
     fn from(value: TileCoord) -> Self {
-        ChunkCoord::new((value.x as f32 / chunk::CHUNK_SIZE as f32).floor() as i32, (value.y as f32 / chunk::CHUNK_SIZE as f32).floor() as i32)
+        ChunkCoord::new(value.x.div_euclid(chunk::CHUNK_SIZE as i32), value.y.div_euclid(chunk::CHUNK_SIZE as i32))
     }
 }
 impl From<Coordinate> for ChunkCoord {
@@ -86,40 +104,375 @@ impl Sub<Coordinate> for Coordinate {
 
 
 
+///things that happened on a surface that other systems may want to react to
+#[derive(Debug, Clone, PartialEq)]
+pub enum SurfaceEvent {
+    ///the tiles at these positions were changed after generation
+    TilesChanged(Vec<TileCoord>),
+}
+
+///shape used by Surface::brush, sizes are radii in tiles
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum Brush {
+    Square(i32),
+    Circle(i32),
+}
+
+///how much work a surface did in one update
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct TickStats {
+    ///chunks that were ticked
+    pub active_chunks: usize,
+    ///loaded chunks that were skipped
+    pub sleeping_chunks: usize,
+}
+
+impl std::ops::AddAssign for TickStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.active_chunks += rhs.active_chunks;
+        self.sleeping_chunks += rhs.sleeping_chunks;
+    }
+}
+
+///chunks within this many chunks of the camera are generated (or reloaded from the cache).
+///each generation stage needs a ring of neighbors, so only the inner chunks get finished
+pub const LOAD_RADIUS: i32 = 6;
+///chunks further than this from the camera are written to the cache and unloaded
+pub const UNLOAD_RADIUS: i32 = 8;
+///chunks within this many chunks of a player are charted for the player's force
+pub const PLAYER_CHART_RADIUS: i32 = 3;
+
 pub struct Surface {
-    pub chunks: Vec<chunk::Chunk>,
-    pub entities: Vec<entity::Entity>,
-    generator: Box<dyn worldgen::Generator>,
+    name: String,
+    pub chunks: HashMap<ChunkCoord, chunk::Chunk>,
+    entities: HashMap<entity::EID, entity::Entity>,
+    index: spatial::SpatialIndex,
+    next_eid: u32,
+    pub players: Vec<player::Player>,
+    ///the tile generator and its overlay passes
+    generator: Arc<worldgen::Pipeline>,
+    ///seed of the map, handed to the generators
+    pub seed: u64,
+    stages: Vec<Box<dyn worldgen::StageGenerator>>,
     pub camera_pos: Coordinate,
+    pub load_radius: i32,
+    pub unload_radius: i32,
+    ///tiles outside this area are never generated. None for an endless surface
+    pub limits: Option<area::TileArea>,
+    cache: chunkcache::ChunkCache,
+    ///chunks that have been handed out by stream_chunks but not generated yet
+    pending: HashSet<ChunkCoord>,
+    ///chunks whose cached copy couldn't be read, with the stage they had reached. they are
+    ///generated again but keep that stage, so the later stages don't place their entities twice
+    regenerating: HashMap<ChunkCoord, chunk::GenStatus>,
+    events: Vec<SurfaceEvent>,
+    ///loaded chunks that get ticked by update. everything else is asleep
+    active_chunks: HashSet<ChunkCoord>,
+    ///number of updates so far
+    tick: u64,
+}
+
+///whether name can be used for a surface. names end up in file names, so they can't contain
+///path separators or ".."
+pub fn is_valid_surface_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['/', '\\', '\0']) && !name.contains("..")
 }
 
 impl Surface {
-    pub fn new<T>(generator: T) -> Surface
+    ///panics if the name isn't valid, see is_valid_surface_name
+    pub fn new<T>(name: &str, generator: T) -> Surface
     where T: worldgen::Generator + 'static {
         Surface {
-            chunks: vec![],
-            entities: vec![],
-            generator: Box::new(generator),
-            camera_pos: Coordinate::new(0.0, 0.0)
+            name: name.to_owned(),
+            chunks: HashMap::new(),
+            entities: HashMap::new(),
+            index: spatial::SpatialIndex::new(),
+            next_eid: 1,
+            players: vec![],
+            generator: Arc::new(worldgen::Pipeline::new(generator)),
+            seed: 0,
+            stages: vec![],
+            camera_pos: Coordinate::new(0.0, 0.0),
+            load_radius: LOAD_RADIUS,
+            unload_radius: UNLOAD_RADIUS,
+            limits: None,
+            cache: chunkcache::ChunkCache::for_surface(name),
+            pending: HashSet::new(),
+            regenerating: HashMap::new(),
+            events: vec![],
+            active_chunks: HashSet::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    ///generates the chunk at coord, unless it already exists. chunks that were unloaded earlier
+    ///are restored from the cache instead of being regenerated. returns whether a chunk was added
+    pub fn gen_chunk(&mut self, coord: ChunkCoord, tileman: &tile::TileManager) -> bool {
+        let existed = self.has_chunk(coord);
+        if self.restore_chunk(coord, tileman) {
+            return !existed;
+        }
+        let chunk = self.generator().gen_chunk(coord, self.seed, tileman);
+        self.insert_generated(chunk)
+    }
+
+    ///makes sure the chunk at coord is on the surface without generating it: it either exists
+    ///already or is restored from the cache. returns false if it still has to be generated
+    pub fn restore_chunk(&mut self, coord: ChunkCoord, tileman: &tile::TileManager) -> bool {
+        if self.has_chunk(coord) {
+            self.pending.remove(&coord);
+            return true;
+        }
+
+        if self.cache.contains(coord) {
+            match self.cache.load(coord, tileman) {
+                Ok(chunk) => {
+                    self.pending.remove(&coord);
+                    self.insert_chunk(chunk);
+                    return true;
+                },
+                Err(e) => {
+                    println!("Failed to reload chunk {:?}, regenerating its tiles: {e}", coord);
+                    if let Some(status) = self.cache.forget(coord) {
+                        self.regenerating.insert(coord, status);
+                    }
+                },
+            }
+        }
+        false
+    }
+
+    ///the tile generator with its overlay passes, to run it somewhere else such as a worker thread
+    pub fn generator(&self) -> Arc<dyn worldgen::Generator> {
+        self.generator.clone()
+    }
+
+    ///adds a chunk made by the surface's generator. returns false, dropping the chunk,
+    ///if the surface got the chunk some other way in the meantime
+    pub fn insert_generated(&mut self, mut chunk: chunk::Chunk) -> bool {
+        self.pending.remove(&chunk.position);
+        if self.has_chunk(chunk.position) {
+            return false;
+        }
+        if let Some(status) = self.regenerating.remove(&chunk.position) {
+            chunk.status = status;
+        }
+        self.insert_chunk(chunk);
+        true
+    }
+
+    ///adds a pass that runs over every chunk after the tile generator. chunks that were
+    ///generated before it was added don't get it
+    pub fn add_overlay<T>(&mut self, overlay: T)
+    where T: worldgen::Overlay + 'static {
+        //generators already handed to worker threads keep the old pipeline
+        Arc::make_mut(&mut self.generator).push(overlay);
+    }
+
+    ///adds a generator for one of the later generation stages. stages with several generators
+    ///run them in the order they were added
+    pub fn add_stage<T>(&mut self, generator: T)
+    where T: worldgen::StageGenerator + 'static {
+        assert!(generator.stage() != chunk::GenStatus::Tiles, "the tile stage is done by the surface generator");
+        self.stages.push(Box::new(generator));
+    }
+
+    ///whether all eight neighbors of the chunk have completed at least the given stage
+    fn neighbors_reached(&self, coord: ChunkCoord, status: chunk::GenStatus) -> bool {
+        area::ChunkArea::around(coord, 1).iter()
+            .filter(|c| *c != coord && self.limits.is_none_or(|l| l.intersects(&(*c).into())))
+            .all(|c| self.get_chunk(c).is_some_and(|n| n.status >= status))
+    }
+
+    ///moves every unfinished chunk whose neighbors are far enough along one stage forward.
+    ///chunks are processed in coordinate order so the result doesn't depend on load order.
+    ///returns the number of chunks that advanced
+    pub fn advance_generation(&mut self, tileman: &tile::TileManager) -> usize {
+        let mut ready: Vec<ChunkCoord> = self.chunks.values()
+            .filter(|c| c.status != chunk::GenStatus::Finished && self.neighbors_reached(c.position, c.status))
+            .map(|c| c.position)
+            .collect();
+        ready.sort_by_key(|c| (c.x, c.y));
+
+        for coord in &ready {
+            let Some(mut chunk) = self.chunks.remove(coord) else { continue; };
+            let Some(next) = chunk.status.next() else { continue; };
+
+            let mut ctx = worldgen::StageContext { chunks: &self.chunks, tileman, entities: vec![] };
+            for stage in self.stages.iter_mut().filter(|s| s.stage() == next) {
+                stage.generate(&mut chunk, &mut ctx);
+            }
+            let entities = ctx.entities;
+
+            chunk.status = next;
+            self.chunks.insert(*coord, chunk);
+            for entity in entities {
+                self.place_entity(entity);
+            }
         }
+        ready.len()
     }
-    pub fn gen_chunk(&mut self, coord: ChunkCoord, tileman: &tile::TileManager) {
-        let chunk = self.generator.gen_chunk(coord, tileman);
-        self.chunks.push(chunk);
+
+    ///the chunks that chunks are loaded around: the camera and every player on the surface
+    pub fn load_centers(&self) -> Vec<ChunkCoord> {
+        let mut centers = vec![self.camera_pos.into()];
+        centers.extend(self.players.iter().map(|p| ChunkCoord::from(p.position)));
+        centers
+    }
+
+    ///the areas each force can currently see: around its players and its radars
+    pub fn chart_sources(&self) -> Vec<(force::ForceId, area::ChunkArea)> {
+        let mut sources: Vec<_> = self.players.iter()
+            .map(|p| (p.force, area::ChunkArea::around(p.position.into(), PLAYER_CHART_RADIUS)))
+            .collect();
+
+        let mut radars: Vec<&entity::Entity> = self.entities.values()
+            .filter(|e| matches!(e.behavior, entity::Behavior::Radar(_)))
+            .collect();
+        radars.sort_by_key(|e| e.id());
+        for radar in radars {
+            if let entity::Behavior::Radar(r) = radar.behavior {
+                sources.push((radar.force, area::ChunkArea::around(radar.position.into(), r.radius)));
+            }
+        }
+        sources
+    }
+
+    ///distance (in chunks) from coord to the closest load center
+    fn load_distance(coord: ChunkCoord, centers: &[ChunkCoord]) -> i32 {
+        centers.iter()
+            .map(|c| (c.x - coord.x).abs().max((c.y - coord.y).abs()))
+            .min()
+            .unwrap_or(i32::MAX)
+    }
+
+    ///unloads chunks that are out of range and returns the missing chunks that should be generated,
+    ///closest first. returned chunks are remembered so they are only requested once
+    pub fn stream_chunks(&mut self, tileman: &tile::TileManager) -> Vec<ChunkCoord> {
+        let centers = self.load_centers();
+
+        let far: Vec<ChunkCoord> = self.chunks.values()
+            .filter(|c| !self.has_active_entities(c.position) && Self::load_distance(c.position, &centers) > self.unload_radius)
+            .map(|c| c.position)
+            .collect();
+        for coord in far {
+            self.unload_chunk(coord, tileman);
+        }
+
+        let mut missing = vec![];
+        for center in &centers {
+            for coord in area::ChunkArea::around(*center, self.load_radius).iter() {
+                let in_limits = self.limits.is_none_or(|l| l.intersects(&coord.into()));
+                if in_limits && !self.has_chunk(coord) && self.pending.insert(coord) {
+                    missing.push(coord);
+                }
+            }
+        }
+        missing.sort_by_key(|c| Self::load_distance(*c, &centers));
+        missing
+    }
+
+    ///writes the chunk to the cache and removes it from the surface.
+    ///if it can't be written the chunk stays loaded
+    pub fn unload_chunk(&mut self, coord: ChunkCoord, tileman: &tile::TileManager) -> bool {
+        let Some(chunk) = self.chunks.get(&coord) else { return false; };
+
+        if let Err(e) = self.cache.store(chunk, tileman) {
+            println!("Failed to unload chunk {:?}: {e}", coord);
+            return false;
+        }
+        self.remove_chunk(coord);
+        true
+    }
+
+    pub fn has_chunk(&self, coord: ChunkCoord) -> bool {
+        self.chunks.contains_key(&coord)
     }
 
     pub fn get_chunk(&self, coord: ChunkCoord) -> Option<&chunk::Chunk> {
-        for c in &self.chunks {
-            if c.position == coord {
-                return Some(c);
+        self.chunks.get(&coord)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_chunk_mut(&mut self, coord: ChunkCoord) -> Option<&mut chunk::Chunk> {
+        self.chunks.get_mut(&coord)
+    }
+
+    ///inserts a chunk at its own position, returning the chunk it replaced (if any)
+    pub fn insert_chunk(&mut self, chunk: chunk::Chunk) -> Option<chunk::Chunk> {
+        let coord = chunk.position;
+        let old = self.chunks.insert(coord, chunk);
+        self.wake_neighborhood(coord);
+        old
+    }
+
+    pub fn remove_chunk(&mut self, coord: ChunkCoord) -> Option<chunk::Chunk> {
+        self.active_chunks.remove(&coord);
+        self.chunks.remove(&coord)
+    }
+
+    ///makes the chunk tick again, if it is loaded
+    pub fn wake_chunk(&mut self, coord: ChunkCoord) {
+        if self.has_chunk(coord) {
+            self.active_chunks.insert(coord);
+        }
+    }
+
+    ///wakes a chunk and the eight chunks around it, for events their contents might react to
+    pub fn wake_neighborhood(&mut self, coord: ChunkCoord) {
+        for c in area::ChunkArea::around(coord, 1).iter() {
+            self.wake_chunk(c);
+        }
+    }
+
+    ///wakes every chunk an area overlaps, plus their neighbors
+    fn wake_area(&mut self, area: area::TileArea) {
+        for c in area.chunks().expand(1).iter() {
+            self.wake_chunk(c);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn is_chunk_active(&self, coord: ChunkCoord) -> bool {
+        self.active_chunks.contains(&coord)
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    ///ticks the active chunks. chunks with nothing left to do and no running entities go to sleep
+    pub fn update(&mut self) -> TickStats {
+        self.tick += 1;
+        let mut active: Vec<ChunkCoord> = self.active_chunks.iter().copied().collect();
+        active.sort_by_key(|c| (c.x, c.y));
+
+        let stats = TickStats {
+            active_chunks: active.len(),
+            sleeping_chunks: self.chunks.len() - active.len(),
+        };
+
+        for coord in active {
+            let busy = self.chunks.get_mut(&coord).is_some_and(|c| c.update());
+            if !busy && !self.has_active_entities(coord) {
+                self.active_chunks.remove(&coord);
             }
         }
+        stats
+    }
 
-        None
+    ///iterates over the generated chunks in area, skipping missing ones
+    pub fn chunks_in(&self, area: area::ChunkArea) -> impl Iterator<Item = &chunk::Chunk> {
+        area.iter().filter_map(|coord| self.get_chunk(coord))
     }
 
     #[allow(dead_code)]
-    pub fn get_tile(&self, coord: TileCoord) -> Option<tile::Tile> {
+    pub fn get_tile(&self, coord: TileCoord) -> Option<tile::TileId> {
         let chunk = self.get_chunk(coord.into());
 
         if let Some(c) = chunk {
@@ -137,4 +490,597 @@ impl Surface {
             return None;
         }
     }
-}
\ No newline at end of file
+
+    ///changes the tile at coord. returns false if the chunk isn't loaded or the tile was already set
+    #[allow(dead_code)]
+    pub fn set_tile(&mut self, coord: TileCoord, tile: tile::TileId) -> bool {
+        self.set_tiles([coord], tile) > 0
+    }
+
+    ///sets every tile in area, returns the number of tiles changed
+    #[allow(dead_code)]
+    pub fn fill_area(&mut self, area: area::TileArea, tile: tile::TileId) -> usize {
+        self.set_tiles(area.iter(), tile)
+    }
+
+    ///paints the tiles covered by brush around center, returns the number of tiles changed
+    #[allow(dead_code)]
+    pub fn brush(&mut self, center: TileCoord, brush: Brush, tile: tile::TileId) -> usize {
+        let (Brush::Square(r) | Brush::Circle(r)) = brush;
+        let coords = area::TileArea::around(center, r).iter()
+            .filter(|c| match brush {
+                Brush::Square(_) => true,
+                Brush::Circle(r) => (c.x - center.x).pow(2) + (c.y - center.y).pow(2) <= r*r,
+            });
+        self.set_tiles(coords, tile)
+    }
+
+    ///puts a tile on top of the one at coord, e.g. a floor. the old tile is restored by remove_top_tile
+    #[allow(dead_code)]
+    pub fn place_tile(&mut self, coord: TileCoord, tile: tile::TileId) -> bool {
+        self.clear_decoratives(area::TileArea::around(coord, 0));
+        self.edit_tiles([coord], |chunk, tcoord| chunk.place_tile(tcoord, tile) == Ok(true)) > 0
+    }
+
+    ///removes the decoratives in area. returns how many were removed
+    pub fn clear_decoratives(&mut self, area: area::TileArea) -> usize {
+        let mut removed = 0;
+        for coord in area.iter() {
+            let Some(chunk) = self.chunks.get_mut(&coord.into()) else { continue; };
+            let count = chunk.remove_decoratives(chunk.position.within_chunk(coord));
+            if count > 0 {
+                chunk.dirty = true;
+                removed += count;
+            }
+        }
+        removed
+    }
+
+    ///removes the top layer at coord, uncovering the tile beneath. returns the removed tile,
+    ///None if there was only the ground
+    #[allow(dead_code)]
+    pub fn remove_top_tile(&mut self, coord: TileCoord) -> Option<tile::TileId> {
+        let mut removed = None;
+        self.edit_tiles([coord], |chunk, tcoord| {
+            removed = chunk.remove_top_tile(tcoord).ok().flatten();
+            removed.is_some()
+        });
+        removed
+    }
+
+    ///the tile covered by the visible one at coord, None if the visible tile is the ground
+    #[allow(dead_code)]
+    pub fn get_hidden_tile(&self, coord: TileCoord) -> Option<tile::TileId> {
+        let chunk = self.get_chunk(coord.into())?;
+        chunk.get_hidden_tile(chunk.position.within_chunk(coord)).ok().flatten()
+    }
+
+    ///sets all coords to tile and emits a single TilesChanged event for the ones that changed
+    fn set_tiles<I: IntoIterator<Item = TileCoord>>(&mut self, coords: I, tile: tile::TileId) -> usize {
+        self.edit_tiles(coords, |chunk, tcoord| chunk.set_tile(tcoord, tile) == Ok(true))
+    }
+
+    ///applies edit to every loaded tile in coords. edit returns whether it changed the tile,
+    ///the changed tiles are reported in a single TilesChanged event
+    fn edit_tiles<I, F>(&mut self, coords: I, mut edit: F) -> usize
+    where I: IntoIterator<Item = TileCoord>, F: FnMut(&mut chunk::Chunk, TileCoord) -> bool {
+        let mut changed = vec![];
+        for coord in coords {
+            let Some(chunk) = self.chunks.get_mut(&coord.into()) else { continue; };
+            let tcoord = chunk.position.within_chunk(coord);
+            if edit(chunk, tcoord) {
+                changed.push(coord);
+            }
+        }
+
+        let count = changed.len();
+        if count > 0 {
+            let chunks: HashSet<ChunkCoord> = changed.iter().map(|c| ChunkCoord::from(*c)).collect();
+            for c in chunks {
+                self.wake_neighborhood(c);
+            }
+            self.events.push(SurfaceEvent::TilesChanged(changed));
+        }
+        count
+    }
+
+    ///returns and clears the events emitted since the last call
+    pub fn take_events(&mut self) -> Vec<SurfaceEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    ///returns the chunks that changed since the last call and clears their dirty flag
+    pub fn take_dirty_chunks(&mut self) -> Vec<ChunkCoord> {
+        let mut dirty = vec![];
+        for chunk in self.chunks.values_mut().filter(|c| c.dirty) {
+            chunk.dirty = false;
+            dirty.push(chunk.position);
+        }
+        dirty
+    }
+
+    ///properties of the tile at coord, None if its chunk isn't loaded
+    pub fn tile_properties<'a>(&self, coord: TileCoord, tileman: &'a tile::TileManager) -> Option<&'a tile::TileProperties> {
+        self.get_tile(coord).and_then(|id| tileman.properties(id))
+    }
+
+    ///whether the tile can be walked on and isn't blocked by a solid entity such as a cliff
+    #[allow(dead_code)]
+    pub fn is_walkable(&self, coord: TileCoord, tileman: &tile::TileManager) -> bool {
+        self.tile_properties(coord, tileman).is_some_and(|p| p.walkable) && !self.is_blocked(coord)
+    }
+
+    ///whether the tile can be built on and isn't blocked by a solid entity such as a cliff
+    #[allow(dead_code)]
+    pub fn is_buildable(&self, coord: TileCoord, tileman: &tile::TileManager) -> bool {
+        self.tile_properties(coord, tileman).is_some_and(|p| p.buildable) && !self.is_blocked(coord)
+    }
+
+    fn is_blocked(&self, coord: TileCoord) -> bool {
+        self.entities_at(coord).iter().any(|e| e.solid)
+    }
+
+    #[allow(dead_code)]
+    pub fn is_water(&self, coord: TileCoord, tileman: &tile::TileManager) -> bool {
+        self.tile_properties(coord, tileman).is_some_and(|p| p.water)
+    }
+
+    ///walking speed multiplier at coord. unloaded tiles can't be walked on
+    #[allow(dead_code)]
+    pub fn speed_modifier(&self, coord: TileCoord, tileman: &tile::TileManager) -> f32 {
+        self.tile_properties(coord, tileman).map_or(0.0, |p| p.speed_modifier)
+    }
+
+    ///adds an entity to the surface, giving it a new id
+    pub fn place_entity(&mut self, mut entity: entity::Entity) -> entity::EID {
+        let id = entity::EID(self.next_eid);
+        self.next_eid += 1;
+        entity.set_id(id);
+
+        //resources lie on the ground, everything else is built over the decoratives
+        if entity.resource_amount.is_none() {
+            self.clear_decoratives(entity.area());
+        }
+
+        self.index.insert(id, entity.area());
+        self.wake_area(entity.area());
+        self.entities.insert(id, entity);
+        id
+    }
+
+    pub fn remove_entity(&mut self, id: entity::EID) -> Option<entity::Entity> {
+        let entity = self.entities.remove(&id)?;
+        self.index.remove(id, entity.area());
+        self.wake_area(entity.area());
+        Some(entity)
+    }
+
+    ///mines up to amount from a resource entity and returns how much was taken.
+    ///the entity is removed once it is depleted
+    #[allow(dead_code)]
+    pub fn mine_resource(&mut self, id: entity::EID, amount: u32) -> u32 {
+        let Some(entity) = self.entities.get_mut(&id) else { return 0; };
+        let taken = entity.mine(amount);
+        if entity.is_depleted() {
+            self.remove_entity(id);
+        }
+        taken
+    }
+
+    ///mines an entity such as a tree or rock, removing it. returns what it yields,
+    ///None if there is no such entity or it can't be mined
+    #[allow(dead_code)]
+    pub fn mine_entity(&mut self, id: entity::EID) -> Option<entity::Minable> {
+        let minable = self.entities.get(&id)?.minable.clone()?;
+        self.remove_entity(id);
+        Some(minable)
+    }
+
+    ///moves an entity to a new origin tile. returns false if there is no such entity
+    #[allow(dead_code)]
+    pub fn move_entity(&mut self, id: entity::EID, position: TileCoord) -> bool {
+        self.update_entity(id, |e| e.position = position)
+    }
+
+    #[allow(dead_code)]
+    pub fn rotate_entity(&mut self, id: entity::EID, direction: direction::Direction) -> bool {
+        self.update_entity(id, |e| e.direction = direction)
+    }
+
+    ///applies a change that may affect the entity's area, keeping the index up to date
+    fn update_entity<F: FnOnce(&mut entity::Entity)>(&mut self, id: entity::EID, f: F) -> bool {
+        let Some(entity) = self.entities.get_mut(&id) else { return false; };
+        let old = entity.area();
+        f(entity);
+        let new = entity.area();
+
+        self.index.remove(id, old);
+        self.index.insert(id, new);
+        self.wake_area(old);
+        self.wake_area(new);
+        true
+    }
+
+    #[allow(dead_code)]
+    pub fn get_entity(&self, id: entity::EID) -> Option<&entity::Entity> {
+        self.entities.get(&id)
+    }
+
+    ///all entities on the surface, in no particular order
+    #[allow(dead_code)]
+    pub fn entities(&self) -> impl Iterator<Item = &entity::Entity> {
+        self.entities.values()
+    }
+
+    ///whether any entity overlapping the chunk is running. such chunks are never unloaded
+    pub fn has_active_entities(&self, coord: ChunkCoord) -> bool {
+        self.index.in_chunk(coord).iter().any(|id| self.entities[id].is_active())
+    }
+
+    ///entities covering the tile
+    #[allow(dead_code)]
+    pub fn entities_at(&self, coord: TileCoord) -> Vec<&entity::Entity> {
+        self.entities_in(area::TileArea::around(coord, 0))
+    }
+
+    ///entities overlapping area, ordered by id
+    pub fn entities_in(&self, area: area::TileArea) -> Vec<&entity::Entity> {
+        self.index.candidates(area.chunks()).iter()
+            .map(|id| &self.entities[id])
+            .filter(|e| e.area().intersects(&area))
+            .collect()
+    }
+
+    ///entities with any part within radius of center, ordered by id.
+    ///radius is in MapPosition units (position::SUBTILES per tile)
+    pub fn entities_in_radius(&self, center: position::MapPosition, radius: i32) -> Vec<&entity::Entity> {
+        let r = position::MapPosition::new(radius, radius);
+        let tiles = area::TileArea::new(center.saturating_sub(r).into(), center.saturating_add(r).into());
+        self.index.candidates(tiles.chunks()).iter()
+            .map(|id| &self.entities[id])
+            .filter(|e| Self::distance_squared_to(center, e) <= radius as i64 * radius as i64)
+            .collect()
+    }
+
+    ///closest entity with the given name within max_radius of center. ties go to the lowest id
+    #[allow(dead_code)]
+    pub fn nearest_entity(&self, center: position::MapPosition, name: &str, max_radius: i32) -> Option<&entity::Entity> {
+        self.entities_in_radius(center, max_radius).into_iter()
+            .filter(|e| e.name == name)
+            .min_by_key(|e| Self::distance_squared_to(center, e))
+    }
+
+    ///squared distance from point to the closest part of the entity, 0 if it's inside
+    fn distance_squared_to(point: position::MapPosition, entity: &entity::Entity) -> i64 {
+        let area = entity.area();
+        let min = position::MapPosition::from(area.min);
+        let max = position::MapPosition::from(area.max.offset(1, 1));
+        let closest = position::MapPosition::new(point.x.clamp(min.x, max.x), point.y.clamp(min.y, max.y));
+        point.distance_squared(closest)
+    }
+}
+
+
+///the tiles and decoratives of the game without their textures, for tests. every tile gets its
+///own map color so rendered maps tell them apart
+#[cfg(test)]
+pub(crate) fn test_tiles() -> tile::TileManager {
+    let mut tileman = tile::TileManager::new();
+    for (i, name) in ["grass", "grass1", "sand", "dirt", "shore", "water", "deepwater"].into_iter().enumerate() {
+        let shade = 30 * i as u8;
+        let water = name.ends_with("water");
+        tileman.add_tile(name, tile::TileProperties {
+            walkable: !water,
+            buildable: !water,
+            water,
+            map_color: tile::MapColor::new(shade, 255 - shade, 100),
+            ..Default::default()
+        });
+    }
+    for decorative in ["grass-tuft", "flowers", "sand-ripples", "pebbles", "dry-grass"] {
+        tileman.add_decorative(decorative);
+    }
+    tileman
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chunk::GenStatus;
+    use worldgen::{LabGen, StageContext, StageGenerator};
+
+    ///puts one marker entity in the corner of every chunk it runs on
+    struct Marker;
+
+    impl StageGenerator for Marker {
+        fn stage(&self) -> GenStatus {
+            GenStatus::Entities
+        }
+
+        fn generate(&mut self, chunk: &mut chunk::Chunk, ctx: &mut StageContext) {
+            ctx.entities.push(entity::Entity::new("marker", chunk.position.into()));
+        }
+    }
+
+    ///a 3x3 chunk surface around the origin with every chunk finished
+    fn finished_surface(name: &str, tileman: &tile::TileManager) -> Surface {
+        let mut surface = Surface::new(name, LabGen);
+        surface.limits = Some(area::ChunkArea::around(ChunkCoord::new(0, 0), 1).tiles());
+        surface.add_stage(Marker);
+        for coord in area::ChunkArea::around(ChunkCoord::new(0, 0), 1).iter() {
+            surface.gen_chunk(coord, tileman);
+        }
+        while surface.advance_generation(tileman) > 0 {}
+        surface
+    }
+
+    ///checks that the index lists exactly the entities overlapping each chunk near the origin
+    fn assert_index_consistent(surface: &Surface) {
+        for coord in area::ChunkArea::around(ChunkCoord::new(0, 0), 3).iter() {
+            let mut expected: Vec<entity::EID> = surface.entities()
+                .filter(|e| e.area().intersects(&coord.into()))
+                .map(|e| e.id())
+                .collect();
+            expected.sort();
+            let mut indexed = surface.index.in_chunk(coord).to_vec();
+            indexed.sort();
+            assert_eq!(indexed, expected, "chunk {coord:?}");
+        }
+    }
+
+    #[test]
+    fn spatial_index_follows_entities() {
+        let mut surface = Surface::new("index-test", LabGen);
+        //one tile wide and four long, so rotating it changes the chunks it covers
+        let mut belt = entity::Entity::new("belt", TileCoord::new(31, 10));
+        belt.footprint = area::TileArea::new(TileCoord::new(0, 0), TileCoord::new(0, 3));
+        let id = surface.place_entity(belt);
+        surface.place_entity(entity::Entity::new("rock", TileCoord::new(-1, -1)));
+        assert_index_consistent(&surface);
+        assert_eq!(surface.index.in_chunk(ChunkCoord::new(0, 0)), &[id]);
+
+        //across the chunk border
+        assert!(surface.move_entity(id, TileCoord::new(33, 10)));
+        assert_index_consistent(&surface);
+        assert!(surface.index.in_chunk(ChunkCoord::new(0, 0)).is_empty());
+        assert_eq!(surface.entities_at(TileCoord::new(33, 13)).len(), 1);
+
+        //facing east it reaches back into the first chunk
+        assert!(surface.rotate_entity(id, direction::Direction::East));
+        assert_index_consistent(&surface);
+        assert_eq!(surface.index.in_chunk(ChunkCoord::new(0, 0)), &[id]);
+        assert_eq!(surface.entities_at(TileCoord::new(30, 10)).len(), 1);
+        assert!(surface.entities_at(TileCoord::new(33, 13)).is_empty());
+
+        assert!(surface.remove_entity(id).is_some());
+        assert_index_consistent(&surface);
+        assert!(surface.index.in_chunk(ChunkCoord::new(1, 0)).is_empty());
+        assert!(!surface.move_entity(id, TileCoord::new(0, 0)));
+    }
+
+    #[test]
+    fn radius_query_near_the_edge_of_the_map() {
+        let mut surface = Surface::new("radius-test", LabGen);
+        let far = TileCoord::new(i32::MAX / position::SUBTILES - 1, 0);
+        surface.place_entity(entity::Entity::new("rock", far));
+        //the box around center reaches past i32::MAX
+        let center = position::MapPosition::tile_center(far);
+        assert_eq!(surface.entities_in_radius(center, 4 * position::SUBTILES).len(), 1);
+        assert_eq!(surface.entities_in_radius(position::MapPosition::new(0, 0), position::SUBTILES).len(), 0);
+    }
+
+    #[test]
+    fn stages_wait_for_neighbors_inside_the_limits() {
+        let tileman = test_tiles();
+        let mut surface = Surface::new("stage-test", LabGen);
+        surface.limits = Some(area::ChunkArea::around(ChunkCoord::new(0, 0), 3).tiles());
+        surface.add_stage(Marker);
+        let status = |s: &Surface, x, y| s.get_chunk(ChunkCoord::new(x, y)).unwrap().status;
+
+        for coord in area::ChunkArea::around(ChunkCoord::new(0, 0), 2).iter() {
+            surface.gen_chunk(coord, &tileman);
+        }
+        while surface.advance_generation(&tileman) > 0 {}
+
+        //the outer ring is missing neighbors that are inside the limits, so it stays behind
+        //and holds back everything within a stage of it
+        assert_eq!(status(&surface, 2, 0), GenStatus::Tiles);
+        assert_eq!(status(&surface, 1, 1), GenStatus::Resources);
+        assert_eq!(status(&surface, 0, 0), GenStatus::Decoratives);
+        assert!(surface.entities.is_empty());
+
+        //chunks at the limits don't wait for the chunks beyond them
+        for coord in area::ChunkArea::around(ChunkCoord::new(0, 0), 3).iter() {
+            surface.gen_chunk(coord, &tileman);
+        }
+        while surface.advance_generation(&tileman) > 0 {}
+        assert!(surface.chunks.values().all(|c| c.status == GenStatus::Finished));
+        assert_eq!(surface.entities.len(), 49);
+    }
+
+    #[test]
+    fn tile_layers_come_off_in_order() {
+        let tileman = test_tiles();
+        let mut surface = Surface::new("layer-test", LabGen);
+        surface.gen_chunk(ChunkCoord::new(0, 0), &tileman);
+        let (dirt, sand) = (tileman.get_id("dirt").unwrap(), tileman.get_id("sand").unwrap());
+        let coord = TileCoord::new(7, 3);
+        let ground = surface.get_tile(coord).unwrap();
+
+        assert!(surface.place_tile(coord, dirt));
+        assert!(surface.place_tile(coord, sand));
+        assert!(!surface.place_tile(coord, sand));
+        assert_eq!(surface.get_tile(coord), Some(sand));
+        assert_eq!(surface.get_hidden_tile(coord), Some(dirt));
+
+        assert_eq!(surface.remove_top_tile(coord), Some(sand));
+        assert_eq!(surface.get_tile(coord), Some(dirt));
+        assert_eq!(surface.get_hidden_tile(coord), Some(ground));
+
+        assert_eq!(surface.remove_top_tile(coord), Some(dirt));
+        assert_eq!(surface.get_tile(coord), Some(ground));
+        assert_eq!(surface.get_hidden_tile(coord), None);
+
+        //the ground itself can't be removed, and the neighbors were never touched
+        assert_eq!(surface.remove_top_tile(coord), None);
+        assert_eq!(surface.get_tile(coord), Some(ground));
+        assert_eq!(surface.get_hidden_tile(coord.offset(1, 0)), None);
+    }
+
+    ///the coords of the single TilesChanged event since the last call, row by row like TileArea::iter
+    fn changed_tiles(surface: &mut Surface) -> Vec<TileCoord> {
+        let events = surface.take_events();
+        assert_eq!(events.len(), 1, "{events:?}");
+        let SurfaceEvent::TilesChanged(mut coords) = events.into_iter().next().unwrap();
+        coords.sort_by_key(|c| (c.y, c.x));
+        coords
+    }
+
+    #[test]
+    fn fill_area_skips_tiles_that_are_already_set() {
+        let tileman = test_tiles();
+        let mut surface = Surface::new("fill-test", LabGen);
+        for coord in area::ChunkArea::around(ChunkCoord::new(0, 0), 1).iter() {
+            surface.gen_chunk(coord, &tileman);
+        }
+        surface.take_dirty_chunks();
+        let sand = tileman.get_id("sand").unwrap();
+
+        let inner = area::TileArea::new(TileCoord::new(0, 0), TileCoord::new(3, 3));
+        assert_eq!(surface.fill_area(inner, sand), 16);
+        assert_eq!(changed_tiles(&mut surface), inner.iter().collect::<Vec<_>>());
+        assert_eq!(surface.take_dirty_chunks(), vec![ChunkCoord::new(0, 0)]);
+
+        //a second fill changes nothing and says so
+        assert_eq!(surface.fill_area(inner, sand), 0);
+        assert!(surface.take_events().is_empty());
+        assert!(surface.take_dirty_chunks().is_empty());
+
+        //only the ring around the first fill is reported
+        let outer = area::TileArea::new(TileCoord::new(-1, -1), TileCoord::new(4, 4));
+        assert_eq!(surface.fill_area(outer, sand), 20);
+        let ring: Vec<TileCoord> = outer.iter().filter(|c| !inner.contains(*c)).collect();
+        assert_eq!(changed_tiles(&mut surface), ring);
+        assert!(outer.iter().all(|c| surface.get_tile(c) == Some(sand)));
+
+        //tiles in chunks that aren't loaded are left out
+        let edge = area::TileArea::new(TileCoord::new(62, 0), TileCoord::new(65, 0));
+        assert_eq!(surface.fill_area(edge, sand), 2);
+        assert_eq!(changed_tiles(&mut surface), vec![TileCoord::new(62, 0), TileCoord::new(63, 0)]);
+    }
+
+    #[test]
+    fn brushes_across_chunk_edges() {
+        let tileman = test_tiles();
+        let mut surface = Surface::new("brush-test", LabGen);
+        for coord in area::ChunkArea::around(ChunkCoord::new(0, 0), 1).iter() {
+            surface.gen_chunk(coord, &tileman);
+        }
+        surface.take_dirty_chunks();
+        let (sand, dirt) = (tileman.get_id("sand").unwrap(), tileman.get_id("dirt").unwrap());
+
+        //centered on the corner shared by four chunks
+        let corner = TileCoord::new(32, 32);
+        assert_eq!(surface.brush(corner, Brush::Square(1), sand), 9);
+        let square: Vec<TileCoord> = area::TileArea::around(corner, 1).iter().collect();
+        assert_eq!(changed_tiles(&mut surface), square);
+        assert!(square.iter().all(|c| surface.get_tile(*c) == Some(sand)));
+        assert_ne!(surface.get_tile(TileCoord::new(34, 32)), Some(sand));
+        let mut dirty = surface.take_dirty_chunks();
+        dirty.sort_by_key(|c| (c.x, c.y));
+        assert_eq!(dirty, vec![ChunkCoord::new(0, 0), ChunkCoord::new(0, 1), ChunkCoord::new(1, 0), ChunkCoord::new(1, 1)]);
+
+        //a circle of radius 2 is 13 tiles, whichever chunks they are in
+        let center = TileCoord::new(0, 0);
+        assert_eq!(surface.brush(center, Brush::Circle(2), dirt), 13);
+        let circle = changed_tiles(&mut surface);
+        assert_eq!(circle.len(), 13);
+        assert!(circle.iter().all(|c| (c.x - center.x).pow(2) + (c.y - center.y).pow(2) <= 4));
+        for coord in [TileCoord::new(-2, 0), TileCoord::new(0, -2), TileCoord::new(-1, -1), TileCoord::new(2, 0)] {
+            assert_eq!(surface.get_tile(coord), Some(dirt), "{coord:?}");
+        }
+        for coord in [TileCoord::new(-2, -2), TileCoord::new(2, -1), TileCoord::new(-3, 0)] {
+            assert_ne!(surface.get_tile(coord), Some(dirt), "{coord:?}");
+        }
+
+        //painting over itself only changes what wasn't painted yet
+        assert_eq!(surface.brush(center, Brush::Square(2), dirt), 12);
+        assert_eq!(changed_tiles(&mut surface).len(), 12);
+    }
+
+    #[test]
+    fn overlays_are_added_per_surface() {
+        let tileman = test_tiles();
+        let sand = tileman.get_id("sand").unwrap();
+        let mut surface = Surface::new("overlay-test", LabGen);
+        let plain = Surface::new("plain-test", LabGen);
+        surface.gen_chunk(ChunkCoord::new(1, 0), &tileman);
+
+        let road = area::TileArea::new(TileCoord::new(-8, 0), TileCoord::new(40, 1));
+        surface.add_overlay(worldgen::TileStamp::new(road, "sand", &tileman).unwrap());
+        assert!(worldgen::TileStamp::new(road, "lava", &tileman).is_err());
+        for x in -1..=1 {
+            surface.gen_chunk(ChunkCoord::new(x, 0), &tileman);
+        }
+
+        //chunks generated before the overlay was added keep their tiles
+        assert!(road.iter().all(|c| (surface.get_tile(c) == Some(sand)) == (c.x < 32)));
+        assert_ne!(surface.get_tile(TileCoord::new(-9, 0)), Some(sand));
+        assert_ne!(surface.get_tile(TileCoord::new(0, 2)), Some(sand));
+        //other surfaces don't get it
+        assert!(plain.generator().gen_chunk(ChunkCoord::new(0, 0), 0, &tileman).tiles.iter().all(|t| *t != sand));
+    }
+
+    #[test]
+    fn idle_chunks_sleep_until_something_changes() {
+        let tileman = test_tiles();
+        let mut surface = Surface::new("sleep-test", LabGen);
+        for coord in area::ChunkArea::around(ChunkCoord::new(0, 0), 2).iter() {
+            surface.gen_chunk(coord, &tileman);
+        }
+        let asleep = TickStats { active_chunks: 0, sleeping_chunks: 25 };
+
+        //new chunks are ticked once, then go to sleep
+        assert_eq!(surface.update().active_chunks, 25);
+        assert_eq!(surface.update(), asleep);
+
+        //a changed tile wakes its chunk and the chunks around it for one tick
+        let sand = tileman.get_id("sand").unwrap();
+        assert!(surface.set_tile(TileCoord::new(40, 40), sand));
+        assert!(surface.is_chunk_active(ChunkCoord::new(1, 1)));
+        assert_eq!(surface.update(), TickStats { active_chunks: 9, sleeping_chunks: 16 });
+        assert_eq!(surface.update(), asleep);
+
+        //so does a new entity, at the corner of the loaded area only the loaded neighbors wake
+        let rock = surface.place_entity(entity::Entity::new("rock", TileCoord::new(-64, -64)));
+        assert_eq!(surface.update(), TickStats { active_chunks: 4, sleeping_chunks: 21 });
+        assert_eq!(surface.update(), asleep);
+
+        //a running entity keeps its chunk awake
+        assert!(surface.remove_entity(rock).is_some());
+        let mut radar = entity::Entity::new("radar", TileCoord::new(5, 5));
+        radar.behavior = entity::Behavior::Radar(entity::RadarBehavior { radius: 1 });
+        surface.place_entity(radar);
+        surface.update();
+        assert_eq!(surface.update(), TickStats { active_chunks: 1, sleeping_chunks: 24 });
+        assert!(surface.is_chunk_active(ChunkCoord::new(0, 0)));
+    }
+
+    #[test]
+    fn unreadable_cache_keeps_generation_status() {
+        let tileman = test_tiles();
+        let mut surface = finished_surface("regenerate-test", &tileman);
+        assert_eq!(surface.entities.len(), 9);
+
+        let center = ChunkCoord::new(0, 0);
+        assert!(surface.unload_chunk(center, &tileman));
+        std::fs::write(surface.cache.path(center), b"garbage").unwrap();
+
+        assert!(!surface.restore_chunk(center, &tileman));
+        assert!(surface.gen_chunk(center, &tileman));
+        assert_eq!(surface.get_chunk(center).unwrap().status, GenStatus::Finished);
+        assert_eq!(surface.advance_generation(&tileman), 0);
+        assert_eq!(surface.entities.len(), 9);
+    }
+}
@@ -0,0 +1,141 @@
+///derives an independent seed from seed and salt, so every noise field of a map gets its own
+pub fn sub_seed(seed: u64, salt: u64) -> u64 {
+    let mut z = seed ^ salt.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+///fbm values are roughly normally distributed around 0 with this standard deviation
+pub const FBM_SPREAD: f64 = 0.205;
+
+///the fbm value that is exceeded on roughly the given share (0..0.5) of the map.
+///uses the rational approximation of the normal quantile from Abramowitz and Stegun (26.2.23)
+pub fn tail_threshold(share: f64) -> f64 {
+    let c = share.clamp(1e-6, 0.5);
+    let t = (-2.0 * c.ln()).sqrt();
+    let z = t - (2.515517 + 0.802853 * t + 0.010328 * t * t) / (1.0 + 1.432788 * t + 0.189269 * t * t + 0.001308 * t * t * t);
+    z * FBM_SPREAD
+}
+
+///a value in 0..1 that only depends on seed and the position
+pub fn random(seed: u64, x: i32, y: i32) -> f64 {
+    let h = sub_seed(sub_seed(seed, x as u64), y as u64);
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+const DIAG: f64 = std::f64::consts::FRAC_1_SQRT_2;
+const GRADIENTS: [(f64, f64); 8] = [
+    (1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0),
+    (DIAG, DIAG), (-DIAG, DIAG), (DIAG, -DIAG), (-DIAG, -DIAG),
+];
+
+///2d gradient noise. the gradient of every lattice point is hashed from the seed and the
+///point itself, so any position can be sampled on its own and always gives the same value
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Noise {
+    seed: u64,
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    fn gradient(&self, x: i64, y: i64) -> (f64, f64) {
+        let h = sub_seed(sub_seed(self.seed, x as u64), y as u64);
+        GRADIENTS[(h >> 61) as usize]
+    }
+
+    ///noise at (x, y), in -1..=1. lattice points are one unit apart
+    pub fn sample(&self, x: f64, y: f64) -> f64 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (ix, iy) = (x0 as i64, y0 as i64);
+
+        let dot = |cx: i64, cy: i64, dx: f64, dy: f64| {
+            let g = self.gradient(cx, cy);
+            g.0 * dx + g.1 * dy
+        };
+        let n00 = dot(ix, iy, fx, fy);
+        let n10 = dot(ix + 1, iy, fx - 1.0, fy);
+        let n01 = dot(ix, iy + 1, fx, fy - 1.0);
+        let n11 = dot(ix + 1, iy + 1, fx - 1.0, fy - 1.0);
+
+        let (u, v) = (fade(fx), fade(fy));
+        let value = lerp(lerp(n00, n10, u), lerp(n01, n11, u), v);
+        (value * std::f64::consts::SQRT_2).clamp(-1.0, 1.0)
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+///several octaves of Noise added together, each at a higher frequency and lower amplitude
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fbm {
+    octaves: Vec<Noise>,
+    ///frequency of the first octave, in lattice points per tile
+    pub frequency: f64,
+    ///frequency multiplier between octaves
+    pub lacunarity: f64,
+    ///amplitude multiplier between octaves
+    pub persistence: f64,
+}
+
+impl Fbm {
+    pub fn new(seed: u64, octaves: u32, frequency: f64) -> Self {
+        Self {
+            octaves: (0..octaves.max(1) as u64).map(|o| Noise::new(sub_seed(seed, o))).collect(),
+            frequency,
+            lacunarity: 2.0,
+            persistence: 0.5,
+        }
+    }
+
+    ///the noise at world position (x, y), in -1..=1
+    pub fn sample(&self, x: f64, y: f64) -> f64 {
+        let (mut freq, mut amp) = (self.frequency, 1.0);
+        let (mut total, mut norm) = (0.0, 0.0);
+        for octave in &self.octaves {
+            total += octave.sample(x * freq, y * freq) * amp;
+            norm += amp;
+            freq *= self.lacunarity;
+            amp *= self.persistence;
+        }
+        total / norm
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deterministic() {
+        let a = Fbm::new(42, 4, 0.03);
+        let b = Fbm::new(42, 4, 0.03);
+        for (x, y) in [(0.5, 0.5), (-100.25, 7.0), (123456.5, -98765.5)] {
+            assert_eq!(a.sample(x, y).to_bits(), b.sample(x, y).to_bits());
+        }
+        assert_ne!(Fbm::new(43, 4, 0.03).sample(10.5, 10.5), a.sample(10.5, 10.5));
+    }
+
+    #[test]
+    fn in_range_and_continuous() {
+        let noise = Noise::new(7);
+        let mut prev = noise.sample(-50.0, 3.3);
+        for i in 1..10_000 {
+            let value = noise.sample(-50.0 + i as f64 * 0.01, 3.3);
+            assert!((-1.0..=1.0).contains(&value));
+            assert!((value - prev).abs() < 0.05);
+            prev = value;
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use super::{force::ForceId, position::MapPosition};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct PlayerId(pub u32);
+
+#[derive(Debug, Clone, Default)]
+pub struct Player {
+    pub id: PlayerId,
+    pub position: MapPosition,
+    pub force: ForceId,
+}
+
+impl Player {
+    #[allow(dead_code)]
+    pub fn new(id: PlayerId, position: MapPosition) -> Self {
+        Self { id, position, force: ForceId::PLAYER }
+    }
+}
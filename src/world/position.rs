@@ -0,0 +1,153 @@
+use std::ops::{Add, Sub};
+
+use super::{chunk::CHUNK_SIZE, ChunkCoord, Coordinate, TileCoord};
+
+///MapPosition units per tile
+pub const SUBTILES: i32 = 256;
+const SUBTILE_BITS: u32 = 8;
+///MapPosition units per chunk
+#[allow(dead_code)]
+pub const CHUNK_SUBTILES: i32 = CHUNK_SIZE as i32 * SUBTILES;
+
+///fixed point world position in 1/256ths of a tile. the simulation uses this instead of
+///Coordinate so it stays exact far from the origin and gives the same results on every platform
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct MapPosition { pub x: i32, pub y: i32 }
+
+impl MapPosition {
+    ///raw constructor, x and y are in 1/256ths of a tile
+    pub fn new(x: i32, y: i32) -> Self { Self {x, y} }
+
+    ///the position of whole tile coordinates
+    pub fn from_tiles(x: i32, y: i32) -> Self {
+        Self::new(x * SUBTILES, y * SUBTILES)
+    }
+
+    ///the center of a tile
+    #[allow(dead_code)]
+    pub fn tile_center(tile: TileCoord) -> Self {
+        MapPosition::from(tile) + MapPosition::new(SUBTILES / 2, SUBTILES / 2)
+    }
+
+    ///position within the tile, 0..SUBTILES on both axes
+    #[allow(dead_code)]
+    pub fn subtile(self) -> (i32, i32) {
+        (self.x & (SUBTILES - 1), self.y & (SUBTILES - 1))
+    }
+
+    ///adds rhs, clamping at the ends of the i32 range instead of overflowing
+    pub fn saturating_add(self, rhs: MapPosition) -> Self {
+        Self::new(self.x.saturating_add(rhs.x), self.y.saturating_add(rhs.y))
+    }
+
+    ///subtracts rhs, clamping at the ends of the i32 range instead of overflowing
+    pub fn saturating_sub(self, rhs: MapPosition) -> Self {
+        Self::new(self.x.saturating_sub(rhs.x), self.y.saturating_sub(rhs.y))
+    }
+
+    ///squared distance in MapPosition units. i64 so it can't overflow
+    pub fn distance_squared(self, other: MapPosition) -> i64 {
+        let dx = (self.x - other.x) as i64;
+        let dy = (self.y - other.y) as i64;
+        dx*dx + dy*dy
+    }
+}
+
+impl From<TileCoord> for MapPosition {
+    ///the top left corner of the tile
+    fn from(value: TileCoord) -> Self {
+        MapPosition::from_tiles(value.x, value.y)
+    }
+}
+impl From<ChunkCoord> for MapPosition {
+    ///the top left corner of the chunk
+    fn from(value: ChunkCoord) -> Self {
+        TileCoord::from(value).into()
+    }
+}
+impl From<Coordinate> for MapPosition {
+    ///rounds to the nearest 1/256th of a tile
+    fn from(value: Coordinate) -> Self {
+        MapPosition::new((value.x * SUBTILES as f32).round() as i32, (value.y * SUBTILES as f32).round() as i32)
+    }
+}
+
+impl From<MapPosition> for TileCoord {
+    fn from(value: MapPosition) -> Self {
+        //arithmetic shift rounds towards negative infinity, so -1/256 is in tile -1
+        TileCoord::new(value.x >> SUBTILE_BITS, value.y >> SUBTILE_BITS)
+    }
+}
+impl From<MapPosition> for ChunkCoord {
+    fn from(value: MapPosition) -> Self {
+        TileCoord::from(value).into()
+    }
+}
+impl From<MapPosition> for Coordinate {
+    ///for rendering only
+    fn from(value: MapPosition) -> Self {
+        Coordinate::new(value.x as f32 / SUBTILES as f32, value.y as f32 / SUBTILES as f32)
+    }
+}
+
+impl Add<MapPosition> for MapPosition {
+    type Output = Self;
+
+    fn add(self, rhs: MapPosition) -> Self::Output {
+        MapPosition::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl Sub<MapPosition> for MapPosition {
+    type Output = Self;
+
+    fn sub(self, rhs: MapPosition) -> Self::Output {
+        MapPosition::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_of_negative_positions() {
+        assert_eq!(TileCoord::from(MapPosition::new(-1, -1)), TileCoord::new(-1, -1));
+        assert_eq!(TileCoord::from(MapPosition::new(-SUBTILES, 0)), TileCoord::new(-1, 0));
+        assert_eq!(TileCoord::from(MapPosition::new(-SUBTILES - 1, 0)), TileCoord::new(-2, 0));
+        assert_eq!(TileCoord::from(MapPosition::new(SUBTILES - 1, 0)), TileCoord::new(0, 0));
+        assert_eq!(MapPosition::new(-1, -SUBTILES - 1).subtile(), (SUBTILES - 1, SUBTILES - 1));
+    }
+
+    #[test]
+    fn chunk_boundaries() {
+        assert_eq!(ChunkCoord::from(MapPosition::new(0, 0)), ChunkCoord::new(0, 0));
+        assert_eq!(ChunkCoord::from(MapPosition::new(CHUNK_SUBTILES - 1, 0)), ChunkCoord::new(0, 0));
+        assert_eq!(ChunkCoord::from(MapPosition::new(CHUNK_SUBTILES, 0)), ChunkCoord::new(1, 0));
+        assert_eq!(ChunkCoord::from(MapPosition::new(-1, 0)), ChunkCoord::new(-1, 0));
+        assert_eq!(ChunkCoord::from(MapPosition::new(-CHUNK_SUBTILES, -CHUNK_SUBTILES - 1)), ChunkCoord::new(-1, -2));
+    }
+
+    #[test]
+    fn round_trips() {
+        for tile in [TileCoord::new(0, 0), TileCoord::new(-1, 5), TileCoord::new(-33, -32), TileCoord::new(1_000_000, -1_000_000)] {
+            assert_eq!(TileCoord::from(MapPosition::from(tile)), tile);
+            assert_eq!(TileCoord::from(MapPosition::tile_center(tile)), tile);
+        }
+        for chunk in [ChunkCoord::new(0, 0), ChunkCoord::new(-1, -1), ChunkCoord::new(-20_000, 20_000)] {
+            assert_eq!(ChunkCoord::from(MapPosition::from(chunk)), chunk);
+        }
+        assert_eq!(MapPosition::from(Coordinate::new(-0.5, 2.25)), MapPosition::new(-128, 576));
+        assert_eq!(Coordinate::from(MapPosition::new(-128, 576)), Coordinate::new(-0.5, 2.25));
+    }
+
+    #[test]
+    fn far_from_origin_is_exact() {
+        let a = MapPosition::from_tiles(3_000_000, -3_000_000);
+        let b = a + MapPosition::new(1, -1);
+        assert_ne!(a, b);
+        assert_eq!(b - a, MapPosition::new(1, -1));
+        assert_eq!(a.distance_squared(b), 2);
+    }
+}
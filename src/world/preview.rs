@@ -0,0 +1,136 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use super::{
+    area::{ChunkArea, TileArea}, chunk::GenStatus, resources::ResourceSpec, tile::{MapColor, TileManager}, Surface,
+};
+
+///color of tiles that haven't been generated
+const UNGENERATED: MapColor = MapColor::new(0, 0, 0);
+
+///generates every chunk in area and runs it through all generation stages. a chunk only
+///finishes once its neighbors are far enough along, so a border of chunks around area is
+///generated as well. returns the number of chunks on the surface afterwards
+pub fn generate(surface: &mut Surface, area: ChunkArea, tileman: &TileManager) -> usize {
+    let margin = GenStatus::ALL.len() as i32 - 1;
+    for coord in area.expand(margin).iter() {
+        if surface.limits.is_none_or(|l| l.intersects(&coord.into())) {
+            surface.gen_chunk(coord, tileman);
+        }
+    }
+    while surface.advance_generation(tileman) > 0 {}
+    surface.chunks.len()
+}
+
+///a picture of part of a map with one pixel per tile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapImage {
+    pub width: u32,
+    pub height: u32,
+    ///rgb, row by row from the top left
+    pub pixels: Vec<u8>,
+}
+
+impl MapImage {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, pixels: vec![0; width as usize * height as usize * 3] }
+    }
+
+    #[allow(dead_code)]
+    pub fn pixel(&self, x: u32, y: u32) -> MapColor {
+        let i = (y as usize * self.width as usize + x as usize) * 3;
+        MapColor::new(self.pixels[i], self.pixels[i + 1], self.pixels[i + 2])
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: MapColor) {
+        let i = (y as usize * self.width as usize + x as usize) * 3;
+        self.pixels[i..i + 3].copy_from_slice(&[color.r, color.g, color.b]);
+    }
+
+    pub fn write_png<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer.write_image_data(&self.pixels).map_err(|e| e.to_string())
+    }
+}
+
+///draws area with the map color of every tile, with the resources from resources on top.
+///resource entities that aren't in resources are left out
+pub fn render(surface: &Surface, area: TileArea, tileman: &TileManager, resources: &[ResourceSpec]) -> MapImage {
+    let mut image = MapImage::new(area.width() as u32, area.height() as u32);
+
+    for tile in area.iter() {
+        let color = surface.get_tile(tile)
+            .and_then(|id| tileman.properties(id))
+            .map_or(UNGENERATED, |p| p.map_color);
+        image.set_pixel((tile.x - area.min.x) as u32, (tile.y - area.min.y) as u32, color);
+    }
+
+    for entity in surface.entities_in(area) {
+        if entity.resource_amount.is_none() {
+            continue;
+        }
+        let Some(spec) = resources.iter().find(|r| r.name == entity.name) else { continue; };
+        let Some(covered) = entity.area().intersection(&area) else { continue; };
+        for tile in covered.iter() {
+            image.set_pixel((tile.x - area.min.x) as u32, (tile.y - area.min.y) as u32, spec.map_color);
+        }
+    }
+    image
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{resources::OreGen, terrain::TerrainGen, test_tiles as tiles, ChunkCoord};
+
+    fn preview(seed: u64) -> MapImage {
+        let tileman = tiles();
+        let mut surface = Surface::new("preview-test", TerrainGen::new(&tileman).unwrap());
+        surface.seed = seed;
+        surface.add_stage(OreGen::new(seed));
+
+        let area = ChunkArea::around(ChunkCoord::new(0, 0), 2);
+        generate(&mut surface, area, &tileman);
+        render(&surface, area.tiles(), &tileman, &ResourceSpec::basic())
+    }
+
+    ///FNV-1a, so the snapshot is a single number
+    fn hash(bytes: &[u8]) -> u64 {
+        bytes.iter().fold(0xcbf29ce484222325, |h, b| (h ^ *b as u64).wrapping_mul(0x100000001b3))
+    }
+
+    #[test]
+    fn preview_is_deterministic() {
+        let image = preview(20240611);
+        assert_eq!((image.width, image.height), (160, 160));
+        assert!(image == preview(20240611));
+        assert!(image != preview(7));
+
+        //the starting patches are drawn over the tiles
+        let iron = ResourceSpec::basic()[0].map_color;
+        assert!((0..160).any(|x| (0..160).any(|y| image.pixel(x, y) == iron)));
+
+        //regression snapshot. if a generator changes on purpose, update this with the new hash
+        assert_eq!(hash(&image.pixels), 15244406313400517607);
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let image = preview(3);
+        let path = std::env::temp_dir().join(format!("preview-{}.png", std::process::id()));
+        image.write_png(&path).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((info.width, info.height), (image.width, image.height));
+        assert!(pixels[..info.buffer_size()] == image.pixels[..]);
+    }
+}
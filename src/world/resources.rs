@@ -0,0 +1,181 @@
+use std::f64::consts::TAU;
+
+use super::{
+    chunk::{Chunk, GenStatus, CHUNK_SIZE}, entity::Entity, noise::{self, Fbm},
+    settings::MapGenSettings, tile::MapColor, worldgen::{StageContext, StageGenerator}, TileCoord,
+};
+
+///how one kind of resource is spread over the map
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceSpec {
+    ///entity name, e.g. "iron-ore"
+    pub name: String,
+    ///roughly how many patches there are. higher means smaller, more frequent patches
+    pub frequency: f64,
+    ///share of the map covered by this resource, 0..1
+    pub coverage: f64,
+    ///amount per tile in the middle of a patch next to spawn
+    pub richness: u32,
+    ///whether the starting area is guaranteed a patch of this resource
+    pub starting: bool,
+    ///color used by the minimap and map previews
+    pub map_color: MapColor,
+}
+
+impl ResourceSpec {
+    pub fn new(name: &str, frequency: f64, coverage: f64, richness: u32, starting: bool) -> Self {
+        Self { name: name.to_owned(), frequency, coverage, richness, starting, map_color: MapColor::new(140, 140, 160) }
+    }
+
+    pub fn with_map_color(mut self, map_color: MapColor) -> Self {
+        self.map_color = map_color;
+        self
+    }
+
+    ///iron, copper, coal and stone, all guaranteed at spawn
+    pub fn basic() -> Vec<ResourceSpec> {
+        vec![
+            ResourceSpec::new("iron-ore", 1.0, 0.05, 600, true).with_map_color(MapColor::new(104, 132, 146)),
+            ResourceSpec::new("copper-ore", 1.0, 0.045, 600, true).with_map_color(MapColor::new(203, 97, 53)),
+            ResourceSpec::new("coal", 0.9, 0.04, 500, true).with_map_color(MapColor::new(20, 20, 20)),
+            ResourceSpec::new("stone", 0.8, 0.03, 450, true).with_map_color(MapColor::new(176, 154, 108)),
+        ]
+    }
+}
+
+///tiles from spawn after which richness has doubled
+const RICHNESS_DISTANCE: f64 = 512.0;
+///radius of the patches placed in the starting area
+const STARTING_PATCH_RADIUS: f64 = 8.0;
+
+///places resource entities in noise shaped patches. runs in the Resources stage.
+///inside the starting area random patches are replaced by one fixed patch per starting resource
+pub struct OreGen {
+    seed: u64,
+    resources: Vec<(ResourceSpec, Fbm)>,
+    pub spawn: TileCoord,
+    ///radius in tiles around spawn that only gets the starting patches
+    pub starting_radius: i32,
+}
+
+impl OreGen {
+    #[allow(dead_code)]
+    pub fn new(seed: u64) -> Self {
+        Self::with_resources(seed, ResourceSpec::basic())
+    }
+
+    pub fn from_settings(settings: &MapGenSettings) -> Self {
+        Self::with_resources(settings.seed, settings.resource_specs())
+    }
+
+    pub fn with_resources(seed: u64, resources: Vec<ResourceSpec>) -> Self {
+        let resources = resources.into_iter().enumerate()
+            .map(|(i, spec)| {
+                let field = Fbm::new(noise::sub_seed(seed, 100 + i as u64), 3, spec.frequency / 160.0);
+                (spec, field)
+            })
+            .collect();
+        Self { seed, resources, spawn: TileCoord::new(0, 0), starting_radius: 80 }
+    }
+
+    ///centers of the starting patches, spread evenly on a ring around spawn with a random twist
+    fn starting_patches(&self) -> Vec<(usize, f64, f64)> {
+        let starting: Vec<usize> = (0..self.resources.len()).filter(|i| self.resources[*i].0.starting).collect();
+        let twist = (noise::sub_seed(self.seed, 99) % 3600) as f64 / 3600.0 * TAU;
+        let ring = self.starting_radius as f64 * 0.45;
+        starting.iter().enumerate()
+            .map(|(n, i)| {
+                let angle = twist + TAU * n as f64 / starting.len() as f64;
+                (*i, self.spawn.x as f64 + angle.cos() * ring, self.spawn.y as f64 + angle.sin() * ring)
+            })
+            .collect()
+    }
+
+    fn distance_to_spawn(&self, x: f64, y: f64) -> f64 {
+        ((x - self.spawn.x as f64).powi(2) + (y - self.spawn.y as f64).powi(2)).sqrt()
+    }
+
+    ///the resource and amount at a tile, if any
+    fn resource_at(&self, tile: TileCoord, starting: &[(usize, f64, f64)]) -> Option<(usize, u32)> {
+        let (x, y) = (tile.x as f64 + 0.5, tile.y as f64 + 0.5);
+        let distance = self.distance_to_spawn(x, y);
+
+        if distance <= self.starting_radius as f64 {
+            return starting.iter().find_map(|(i, cx, cy)| {
+                let d = ((x - cx).powi(2) + (y - cy).powi(2)).sqrt();
+                let strength = 1.0 - d / STARTING_PATCH_RADIUS;
+                (strength > 0.0).then(|| (*i, ((0.5 + strength) * self.resources[*i].0.richness as f64) as u32))
+            });
+        }
+
+        let scale = 1.0 + distance / RICHNESS_DISTANCE;
+        self.resources.iter().enumerate().find_map(|(i, (spec, field))| {
+            let threshold = noise::tail_threshold(spec.coverage);
+            let value = field.sample(x, y);
+            (value > threshold).then(|| {
+                let strength = ((value - threshold) / noise::FBM_SPREAD).min(1.0);
+                (i, ((0.5 + strength) * spec.richness as f64 * scale).max(1.0) as u32)
+            })
+        })
+    }
+}
+
+impl StageGenerator for OreGen {
+    fn stage(&self) -> GenStatus {
+        GenStatus::Resources
+    }
+
+    fn generate(&mut self, chunk: &mut Chunk, ctx: &mut StageContext) {
+        let starting = self.starting_patches();
+        let origin: TileCoord = chunk.position.into();
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 0..CHUNK_SIZE as i32 {
+                let tile = origin.offset(x, y);
+                let Some((i, amount)) = self.resource_at(tile, &starting) else { continue; };
+                let water = chunk.get_tile(TileCoord::new(x, y)).ok()
+                    .and_then(|t| ctx.tileman.properties(t))
+                    .is_some_and(|p| p.water);
+                if water {
+                    continue;
+                }
+
+                let mut entity = Entity::new(&self.resources[i].0.name, tile);
+                entity.resource_amount = Some(amount);
+                ctx.entities.push(entity);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+    use crate::world::{area::TileArea, terrain::TerrainGen, test_tiles, worldgen::Generator};
+
+    #[test]
+    fn basic_ores_in_the_starting_area() {
+        let tileman = test_tiles();
+        let terrain = TerrainGen::new(&tileman).unwrap();
+        let chunks = HashMap::new();
+        for seed in [1, 42, 777, 20240611, 987654321] {
+            let mut ores = OreGen::new(seed);
+            let radius = ores.starting_radius;
+            let mut ctx = StageContext { chunks: &chunks, tileman: &tileman, entities: vec![] };
+            for coord in TileArea::around(ores.spawn, radius).chunks().iter() {
+                let mut chunk = terrain.gen_chunk(coord, seed, &tileman);
+                ores.generate(&mut chunk, &mut ctx);
+            }
+
+            let found: HashSet<&str> = ctx.entities.iter()
+                .filter(|e| (e.position.x - ores.spawn.x).pow(2) + (e.position.y - ores.spawn.y).pow(2) <= radius * radius)
+                .map(|e| e.name.as_str())
+                .collect();
+            for spec in ResourceSpec::basic() {
+                assert!(found.contains(spec.name.as_str()), "no {} near spawn with seed {seed}", spec.name);
+            }
+        }
+    }
+}
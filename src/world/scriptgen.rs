@@ -0,0 +1,330 @@
+use std::{path::Path, sync::{Arc, Mutex}};
+
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope, AST, FLOAT, INT};
+
+use super::{
+    chunk::{Chunk, GenStatus, CHUNK_SIZE}, entity::Entity, noise::{self, Fbm, Noise}, tile::{TileId, TileManager},
+    worldgen::{Generator, StageContext, StageGenerator}, ChunkCoord, TileCoord,
+};
+
+const TILE_FN: &str = "tile";
+const ENTITIES_FN: &str = "entities";
+///operations a single call into a script may take, so a script stuck in a loop can't hang generation
+const MAX_OPERATIONS: u64 = 1_000_000;
+///noise layers of scripts are salted from here, away from the built in fields
+const LAYER_SALT: u64 = 1000;
+
+///what a script sees as `this`
+#[derive(Debug, Clone)]
+struct ScriptContext {
+    seed: u64,
+    chunk: ChunkCoord,
+    tiles: Vec<String>,
+}
+
+impl ScriptContext {
+    fn layer(&self, layer: INT) -> u64 {
+        noise::sub_seed(self.seed, LAYER_SALT.wrapping_add(layer as u64))
+    }
+}
+
+///scripts write coordinates as integers and frequencies as floats, either is fine everywhere
+fn number(value: &Dynamic) -> Result<f64, Box<EvalAltResult>> {
+    if let Ok(f) = value.as_float() {
+        return Ok(f);
+    }
+    value.as_int().map(|i| i as f64).map_err(|t| format!("expected a number, got {t}").into())
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    //the fast path for operators drops the position of errors like division by zero
+    engine.set_fast_operators(false);
+
+    engine.register_type_with_name::<ScriptContext>("Chunk")
+        .register_get("seed", |c: &mut ScriptContext| c.seed as INT)
+        .register_get("chunk_x", |c: &mut ScriptContext| c.chunk.x as INT)
+        .register_get("chunk_y", |c: &mut ScriptContext| c.chunk.y as INT)
+        .register_get("x", |c: &mut ScriptContext| TileCoord::from(c.chunk).x as INT)
+        .register_get("y", |c: &mut ScriptContext| TileCoord::from(c.chunk).y as INT)
+        .register_get("size", |_: &mut ScriptContext| CHUNK_SIZE as INT)
+        .register_get("tiles", |c: &mut ScriptContext| c.tiles.iter().map(|t| Dynamic::from(t.clone())).collect::<Array>())
+        .register_fn("has_tile", |c: &mut ScriptContext, name: &str| c.tiles.iter().any(|t| t.eq_ignore_ascii_case(name)))
+        .register_fn("noise", |c: &mut ScriptContext, layer: INT, x: Dynamic, y: Dynamic, frequency: Dynamic| -> Result<FLOAT, Box<EvalAltResult>> {
+            let f = number(&frequency)?;
+            Ok(Noise::new(c.layer(layer)).sample(number(&x)? * f, number(&y)? * f))
+        })
+        .register_fn("fbm", |c: &mut ScriptContext, layer: INT, x: Dynamic, y: Dynamic, octaves: INT, frequency: Dynamic| -> Result<FLOAT, Box<EvalAltResult>> {
+            let fbm = Fbm::new(c.layer(layer), octaves.clamp(1, 16) as u32, number(&frequency)?);
+            Ok(fbm.sample(number(&x)?, number(&y)?))
+        })
+        .register_fn("random", |c: &mut ScriptContext, layer: INT, x: INT, y: INT| {
+            noise::random(c.layer(layer), x as i32, y as i32)
+        });
+    engine
+}
+
+///a compiled script, shared by the tile generator and the entity stage
+struct Script {
+    ///file name of the script, used in error messages
+    name: String,
+    engine: Engine,
+    ast: AST,
+    last_error: Mutex<Option<String>>,
+}
+
+impl Script {
+    fn has_fn(&self, name: &str, params: usize) -> bool {
+        self.ast.iter_functions().any(|f| f.name == name && f.params.len() == params)
+    }
+
+    ///calls a function of the script with this bound to ctx
+    fn call<T: Clone + Send + Sync + 'static>(&self, name: &str, ctx: &mut Dynamic, args: impl FuncArgs) -> Result<T, String> {
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(ctx);
+        self.engine.call_fn_with_options(options, &mut Scope::new(), &self.ast, name, args)
+            .map_err(|e| self.describe(*e))
+    }
+
+    ///"script:line: message", pointing at where the error happened rather than where the
+    ///generator called into the script
+    fn describe(&self, mut err: EvalAltResult) -> String {
+        let mut line = err.position().line();
+        while let EvalAltResult::ErrorInFunctionCall(_, _, inner, _) = err {
+            err = *inner;
+            line = err.position().line().or(line);
+        }
+        err.clear_position();
+        match line {
+            Some(line) => format!("{}:{line}: {err}", self.name),
+            None => format!("{}: {err}", self.name),
+        }
+    }
+
+    fn report(&self, err: String) {
+        println!("Worldgen script error: {err}");
+        *self.last_error.lock().unwrap() = Some(err);
+    }
+
+    fn context(seed: u64, chunk: ChunkCoord, tileman: &TileManager) -> Dynamic {
+        Dynamic::from(ScriptContext { seed, chunk, tiles: tileman.tile_names().map(str::to_owned).collect() })
+    }
+}
+
+///tiles picked by the tile(x, y) function of a rhai script. scripts/worldgen.rhai shows what
+///scripts can use. a tile the script fails on, or names a tile that doesn't exist, becomes default_tile
+pub struct ScriptGen {
+    script: Arc<Script>,
+    pub default_tile: String,
+}
+
+impl ScriptGen {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ScriptGen, String> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let name = path.file_name().map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().into_owned());
+        Self::compile(&name, &source)
+    }
+
+    ///compiles source, calling the script name in error messages
+    pub fn compile(name: &str, source: &str) -> Result<ScriptGen, String> {
+        let engine = engine();
+        let mut ast = engine.compile(source).map_err(|e| match e.1.line() {
+            Some(line) => format!("{name}:{line}: {}", e.0),
+            None => format!("{name}: {}", e.0),
+        })?;
+        ast.set_source(name);
+
+        let script = Script { name: name.to_owned(), engine, ast, last_error: Mutex::new(None) };
+        if !script.has_fn(TILE_FN, 2) {
+            return Err(format!("{name}: there is no {TILE_FN}(x, y) function"));
+        }
+        Ok(ScriptGen { script: Arc::new(script), default_tile: String::from("grass") })
+    }
+
+    #[allow(dead_code)]
+    pub fn with_default_tile(mut self, tile: &str) -> Self {
+        self.default_tile = tile.to_owned();
+        self
+    }
+
+    ///the stage placing the entities from the script's entities function
+    pub fn entities(&self, seed: u64) -> ScriptEntities {
+        ScriptEntities { script: self.script.clone(), seed }
+    }
+
+    ///the last error the script ran into, if any
+    #[allow(dead_code)]
+    pub fn last_error(&self) -> Option<String> {
+        self.script.last_error.lock().unwrap().clone()
+    }
+
+    fn tile(&self, ctx: &mut Dynamic, tile: TileCoord, tileman: &TileManager) -> Result<TileId, String> {
+        let name: rhai::ImmutableString = self.script.call(TILE_FN, ctx, (tile.x as INT, tile.y as INT))?;
+        tileman.get_id(&name).map_err(|_| format!("{}: {TILE_FN}({}, {}) returned unknown tile \"{name}\"", self.script.name, tile.x, tile.y))
+    }
+}
+
+impl Generator for ScriptGen {
+    fn gen_chunk(&self, position: ChunkCoord, seed: u64, tileman: &TileManager)-> Chunk {
+        let fallback = tileman.get_id(&self.default_tile).unwrap_or_else(|_| {
+            self.script.report(format!("{}: default tile \"{}\" isn't registered", self.script.name, self.default_tile));
+            TileId::default()
+        });
+        let mut ctx = Script::context(seed, position, tileman);
+
+        let origin: TileCoord = position.into();
+        let mut tiles = [fallback; CHUNK_SIZE*CHUNK_SIZE];
+        let (mut first_error, mut failed) = (None, 0);
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                match self.tile(&mut ctx, origin.offset(x as i32, y as i32), tileman) {
+                    Ok(id) => tiles[x*CHUNK_SIZE+y] = id,
+                    Err(e) => {
+                        first_error.get_or_insert(e);
+                        failed += 1;
+                    }
+                }
+            }
+        }
+        //one report per chunk, a broken tile function usually fails on every tile
+        if let Some(err) = first_error {
+            self.script.report(format!("{err} ({failed} tiles of chunk ({}, {}) fell back to {})", position.x, position.y, self.default_tile));
+        }
+        Chunk::new(position, tiles)
+    }
+}
+
+///places the entities returned by a script's entities function. runs in the Entities stage.
+///entities outside the chunk being generated are left out
+pub struct ScriptEntities {
+    script: Arc<Script>,
+    seed: u64,
+}
+
+impl ScriptEntities {
+    fn entity(&self, value: Dynamic) -> Result<Entity, String> {
+        let map = value.try_cast::<Map>().ok_or_else(|| format!("{}: {ENTITIES_FN}() must return maps", self.script.name))?;
+        let get = |key: &str| map.get(key).cloned();
+        let name = get("name").and_then(|n| n.into_string().ok())
+            .ok_or_else(|| format!("{}: entity without a name", self.script.name))?;
+        let (Some(x), Some(y)) = (get("x").and_then(|v| v.as_int().ok()), get("y").and_then(|v| v.as_int().ok())) else {
+            return Err(format!("{}: entity {name} needs integer x and y", self.script.name));
+        };
+
+        let mut entity = Entity::new(&name, TileCoord::new(x as i32, y as i32));
+        entity.resource_amount = get("amount").and_then(|v| v.as_int().ok()).map(|a| a.max(0) as u32);
+        entity.solid = get("solid").and_then(|v| v.as_bool().ok()).unwrap_or(false);
+        Ok(entity)
+    }
+}
+
+impl StageGenerator for ScriptEntities {
+    fn stage(&self) -> GenStatus {
+        GenStatus::Entities
+    }
+
+    fn generate(&mut self, chunk: &mut Chunk, ctx: &mut StageContext) {
+        if !self.script.has_fn(ENTITIES_FN, 0) {
+            return;
+        }
+        let mut this = Script::context(self.seed, chunk.position, ctx.tileman);
+        let entities: Array = match self.script.call(ENTITIES_FN, &mut this, ()) {
+            Ok(entities) => entities,
+            Err(e) => return self.script.report(e),
+        };
+
+        for value in entities {
+            match self.entity(value) {
+                Ok(entity) if ChunkCoord::from(entity.position) == chunk.position => ctx.entities.push(entity),
+                Ok(_) => {},
+                Err(e) => self.script.report(e),
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{area::ChunkArea, test_tiles as tiles, Surface};
+
+    #[test]
+    fn script_picks_tiles() {
+        let tileman = tiles();
+        let gen = ScriptGen::compile("test.rhai", r#"
+            fn tile(x, y) {
+                if x == this.x && y == this.y { return "sand"; }
+                if this.fbm(1, x, y, 3, 1.0 / 64.0) < -0.1 { "water" } else { "grass" }
+            }
+        "#).unwrap();
+
+        let a = gen.gen_chunk(ChunkCoord::new(2, -1), 7, &tileman);
+        assert_eq!(a.get_tile(TileCoord::new(0, 0)), tileman.get_id("sand"));
+        assert!(a.tiles == gen.gen_chunk(ChunkCoord::new(2, -1), 7, &tileman).tiles);
+        let all: Vec<_> = ChunkArea::around(ChunkCoord::new(0, 0), 3).iter().flat_map(|c| gen.gen_chunk(c, 7, &tileman).tiles).collect();
+        let water = |t: &TileId| tileman.get_id("water") == Ok(*t);
+        assert!(all.iter().any(water) && !all.iter().all(water));
+        assert_eq!(gen.last_error(), None);
+    }
+
+    #[test]
+    fn script_errors_fall_back() {
+        let tileman = tiles();
+        let gen = ScriptGen::compile("broken.rhai", "fn tile(x, y) {\n    if x < 4 { return \"lava\"; }\n    if y > 4 { return x / 0; }\n    x.nope()\n}").unwrap()
+            .with_default_tile("sand");
+        let chunk = gen.gen_chunk(ChunkCoord::new(0, 0), 1, &tileman);
+        assert!(chunk.tiles.iter().all(|t| *t == tileman.get_id("sand").unwrap()));
+        assert!(gen.last_error().unwrap().starts_with("broken.rhai: tile(0, 0) returned unknown tile \"lava\""));
+
+        gen.gen_chunk(ChunkCoord::new(1, 0), 1, &tileman);
+        assert!(gen.last_error().unwrap().starts_with("broken.rhai:4: Function not found: nope"), "{:?}", gen.last_error());
+        gen.gen_chunk(ChunkCoord::new(1, 1), 1, &tileman);
+        assert!(gen.last_error().unwrap().starts_with("broken.rhai:3: Division by zero"), "{:?}", gen.last_error());
+
+        let err = ScriptGen::compile("syntax.rhai", "fn tile(x, y) {\n    let = 4;\n}").err().unwrap();
+        assert!(err.starts_with("syntax.rhai:2: "), "{err}");
+        assert!(ScriptGen::compile("empty.rhai", "fn other() {}").is_err());
+
+        let gen = ScriptGen::compile("lava.rhai", "fn tile(x, y) { \"grass\" }").unwrap().with_default_tile("lava");
+        gen.gen_chunk(ChunkCoord::new(0, 0), 1, &tileman);
+        assert_eq!(gen.last_error().as_deref(), Some("lava.rhai: default tile \"lava\" isn't registered"));
+    }
+
+    #[test]
+    fn script_places_entities() {
+        let tileman = tiles();
+        let gen = ScriptGen::compile("trees.rhai", r#"
+            fn tile(x, y) { "grass" }
+            fn entities() {
+                [#{ name: "tree", x: this.x + 1, y: this.y + 2, solid: true },
+                 #{ name: "iron-ore", x: this.x, y: this.y, amount: 300 },
+                 #{ name: "tree", x: this.x - 1, y: this.y }]
+            }
+        "#).unwrap();
+        let entities = gen.entities(3);
+
+        let mut surface = Surface::new("script-test", gen);
+        surface.add_stage(entities);
+        for coord in ChunkArea::around(ChunkCoord::new(0, 0), 4).iter() {
+            surface.gen_chunk(coord, &tileman);
+        }
+        while surface.advance_generation(&tileman) > 0 {}
+
+        let tree = surface.entities_at(TileCoord::new(1, 2));
+        assert!(tree.len() == 1 && tree[0].name == "tree" && tree[0].solid);
+        let ore = surface.entities_at(TileCoord::new(0, 0));
+        assert!(ore.len() == 1 && ore[0].resource_amount == Some(300));
+        //the third entity belongs to the chunk to the west and is dropped
+        assert!(surface.entities_at(TileCoord::new(-1, 0)).is_empty());
+    }
+
+    #[test]
+    fn example_script_compiles() {
+        let tileman = tiles();
+        let gen = ScriptGen::compile("worldgen.rhai", include_str!("../../scripts/worldgen.rhai")).unwrap();
+        gen.gen_chunk(ChunkCoord::new(0, 0), 1, &tileman);
+        assert_eq!(gen.last_error(), None);
+    }
+}
@@ -0,0 +1,362 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::{area::TileArea, resources::ResourceSpec, tile::TileManager, worldgen::TileStamp, TileCoord};
+
+///multipliers on how a resource is spread over the map. 1 is the default for every field
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourceSettings {
+    ///how many patches there are
+    pub frequency: f64,
+    ///how big the patches are
+    pub size: f64,
+    ///how much each tile holds
+    pub richness: f64,
+}
+
+impl Default for ResourceSettings {
+    fn default() -> Self {
+        Self { frequency: 1.0, size: 1.0, richness: 1.0 }
+    }
+}
+
+///an area of the map covered with one tile when it is generated, see worldgen::TileStamp
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StampSettings {
+    pub tile: String,
+    ///corners of the area, both inclusive
+    pub min: [i32; 2],
+    pub max: [i32; 2],
+}
+
+///everything that decides what a map looks like. two maps made from equal settings are identical
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MapGenSettings {
+    pub seed: u64,
+    ///size of the map in tiles, centered on spawn. 0 means unlimited
+    pub width: u32,
+    pub height: u32,
+    ///multiplier on how much of the map is covered by water. 0 means no lakes or rivers
+    pub water: f64,
+    ///by resource name. names that aren't basic resources add a new resource
+    pub resources: BTreeMap<String, ResourceSettings>,
+    ///stamped over the terrain in this order
+    pub stamps: Vec<StampSettings>,
+}
+
+impl Default for MapGenSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            width: 0,
+            height: 0,
+            water: 1.0,
+            resources: ResourceSpec::basic().into_iter()
+                .map(|r| (r.name, ResourceSettings::default()))
+                .collect(),
+            stamps: vec![],
+        }
+    }
+}
+
+const EXCHANGE_VERSION: u8 = 2;
+///the exchange string stores counts and name lengths in a byte
+const MAX_EXCHANGE_LEN: usize = u8::MAX as usize;
+
+impl MapGenSettings {
+    ///reads settings saved by save. fails if they aren't valid, see validate
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<MapGenSettings, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let settings: MapGenSettings = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    ///checks that the settings fit in a map exchange string: at most 255 resources and 255 stamps,
+    ///with resource and tile names of 1 to 255 bytes
+    pub fn validate(&self) -> Result<(), String> {
+        if self.resources.len() > MAX_EXCHANGE_LEN {
+            return Err(format!("too many resources, at most {MAX_EXCHANGE_LEN} are allowed"));
+        }
+        if self.stamps.len() > MAX_EXCHANGE_LEN {
+            return Err(format!("too many stamps, at most {MAX_EXCHANGE_LEN} are allowed"));
+        }
+        for name in self.resources.keys() {
+            if name.is_empty() || name.len() > MAX_EXCHANGE_LEN {
+                return Err(format!("resource name \"{name}\" must be 1 to {MAX_EXCHANGE_LEN} bytes long"));
+            }
+        }
+        for stamp in &self.stamps {
+            if stamp.tile.is_empty() || stamp.tile.len() > MAX_EXCHANGE_LEN {
+                return Err(format!("stamp tile \"{}\" must be 1 to {MAX_EXCHANGE_LEN} bytes long", stamp.tile));
+            }
+        }
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| e.to_string())
+    }
+
+    ///the tiles that may be generated, None for an unlimited map
+    pub fn limits(&self) -> Option<TileArea> {
+        if self.width == 0 && self.height == 0 {
+            return None;
+        }
+        //first and last tile on one axis. an odd size puts the extra tile on the positive side
+        let span = |size: u32| {
+            if size == 0 {
+                return (-(i32::MAX / 2), i32::MAX / 2 - 1);
+            }
+            (-((size / 2) as i32), (size - size / 2 - 1) as i32)
+        };
+        let ((x0, x1), (y0, y1)) = (span(self.width), span(self.height));
+        Some(TileArea::new(TileCoord::new(x0, y0), TileCoord::new(x1, y1)))
+    }
+
+    ///the resources to generate: the basic ones and any extra ones named in the settings,
+    ///scaled by their multipliers
+    pub fn resource_specs(&self) -> Vec<ResourceSpec> {
+        let mut specs = ResourceSpec::basic();
+        for name in self.resources.keys() {
+            if !specs.iter().any(|s| s.name == *name) {
+                specs.push(ResourceSpec::new(name, 1.0, 0.02, 400, false));
+            }
+        }
+        for spec in &mut specs {
+            let s = self.resources.get(&spec.name).copied().unwrap_or_default();
+            spec.frequency *= s.frequency;
+            spec.coverage *= s.size;
+            spec.richness = (spec.richness as f64 * s.richness).round() as u32;
+        }
+        specs.retain(|s| s.coverage > 0.0 && s.richness > 0);
+        specs
+    }
+
+    ///the overlay passes for stamps. fails if a stamp's tile isn't registered
+    pub fn tile_stamps(&self, tileman: &TileManager) -> Result<Vec<TileStamp>, String> {
+        self.stamps.iter()
+            .map(|s| {
+                let area = TileArea::new(TileCoord::new(s.min[0], s.min[1]), TileCoord::new(s.max[0], s.max[1]));
+                TileStamp::new(area, &s.tile, tileman)
+            })
+            .collect()
+    }
+
+    ///encodes the settings as a short string of printable characters to share with others.
+    ///fails if they aren't valid, see validate
+    pub fn to_exchange_string(&self) -> Result<String, String> {
+        self.validate()?;
+        let mut bytes = vec![EXCHANGE_VERSION];
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.width.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        bytes.extend_from_slice(&self.water.to_le_bytes());
+        bytes.push(self.resources.len() as u8);
+        for (name, r) in &self.resources {
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name.as_bytes());
+            for value in [r.frequency, r.size, r.richness] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        bytes.push(self.stamps.len() as u8);
+        for stamp in &self.stamps {
+            bytes.push(stamp.tile.len() as u8);
+            bytes.extend_from_slice(stamp.tile.as_bytes());
+            for value in stamp.min.iter().chain(&stamp.max) {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        Ok(encode_exchange_bytes(bytes))
+    }
+
+    ///decodes a string made by to_exchange_string. whitespace (e.g. from line wrapping) is ignored
+    pub fn from_exchange_string(text: &str) -> Result<MapGenSettings, String> {
+        let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let body = text.strip_prefix(">>>").and_then(|t| t.strip_suffix("<<<"))
+            .ok_or("missing >>> and <<< around the map exchange string")?;
+        let bytes = base64_decode(body).ok_or("map exchange string contains invalid characters")?;
+
+        let (data, sum) = bytes.split_at(bytes.len().checked_sub(4).ok_or("map exchange string is too short")?);
+        if checksum(data).to_le_bytes() != sum {
+            return Err("map exchange string is damaged".to_owned());
+        }
+
+        let mut r = Reader { bytes: data, pos: 0 };
+        if r.take(1)?[0] != EXCHANGE_VERSION {
+            return Err("map exchange string is from a different version".to_owned());
+        }
+        let mut settings = MapGenSettings {
+            seed: u64::from_le_bytes(r.array()?),
+            width: u32::from_le_bytes(r.array()?),
+            height: u32::from_le_bytes(r.array()?),
+            water: f64::from_le_bytes(r.array()?),
+            resources: BTreeMap::new(),
+            stamps: vec![],
+        };
+        for _ in 0..r.take(1)?[0] {
+            let len = r.take(1)?[0] as usize;
+            let name = String::from_utf8(r.take(len)?.to_vec()).map_err(|_| "bad resource name")?;
+            let resource = ResourceSettings {
+                frequency: f64::from_le_bytes(r.array()?),
+                size: f64::from_le_bytes(r.array()?),
+                richness: f64::from_le_bytes(r.array()?),
+            };
+            settings.resources.insert(name, resource);
+        }
+        for _ in 0..r.take(1)?[0] {
+            let len = r.take(1)?[0] as usize;
+            let tile = String::from_utf8(r.take(len)?.to_vec()).map_err(|_| "bad stamp tile name")?;
+            let mut corners = [0; 4];
+            for value in &mut corners {
+                *value = i32::from_le_bytes(r.array()?);
+            }
+            settings.stamps.push(StampSettings { tile, min: [corners[0], corners[1]], max: [corners[2], corners[3]] });
+        }
+        if r.pos != data.len() {
+            return Err("map exchange string has extra data after the settings".to_owned());
+        }
+        Ok(settings)
+    }
+}
+
+///appends the checksum and wraps the bytes in the >>> <<< markers
+fn encode_exchange_bytes(mut bytes: Vec<u8>) -> String {
+    bytes.extend_from_slice(&checksum(&bytes).to_le_bytes());
+    format!(">>>{}<<<", base64_encode(&bytes))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let slice = self.bytes.get(self.pos..self.pos + len).ok_or("map exchange string is too short")?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+}
+
+///fnv-1a, to catch typos and truncated copies
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |h, b| (h ^ *b as u32).wrapping_mul(0x0100_0193))
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for group in bytes.chunks(3) {
+        let n = group.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..=group.len() {
+            out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    out
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    for group in text.as_bytes().chunks(4) {
+        if group.len() < 2 {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, c) in group.iter().enumerate() {
+            n |= (BASE64.iter().position(|b| b == c)? as u32) << (18 - 6 * i);
+        }
+        for i in 0..group.len() - 1 {
+            out.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(out)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exchange_string_round_trip() {
+        let mut settings = MapGenSettings { seed: 987654321, width: 2000, height: 0, water: 0.5, ..Default::default() };
+        settings.resources.get_mut("coal").unwrap().richness = 2.5;
+        settings.resources.insert("uranium-ore".to_owned(), ResourceSettings { frequency: 0.3, size: 0.1, richness: 1.0 });
+
+        let text = settings.to_exchange_string().unwrap();
+        assert!(text.chars().all(|c| c.is_ascii_graphic()));
+        assert_eq!(MapGenSettings::from_exchange_string(&text), Ok(settings.clone()));
+
+        //wrapped over several lines
+        let wrapped: String = text.chars().collect::<Vec<_>>().chunks(20).map(|c| c.iter().collect::<String>() + "\n").collect();
+        assert_eq!(MapGenSettings::from_exchange_string(&wrapped), Ok(settings));
+    }
+    #[test]
+    fn exchange_string_limits() {
+        let mut settings = MapGenSettings::default();
+        settings.resources.insert("é".repeat(128), ResourceSettings::default());
+        assert!(settings.validate().is_err());
+        assert!(settings.to_exchange_string().is_err());
+
+        let mut settings = MapGenSettings { resources: BTreeMap::new(), ..Default::default() };
+        for i in 0..=MAX_EXCHANGE_LEN {
+            settings.resources.insert(format!("ore-{i}"), ResourceSettings::default());
+        }
+        assert!(settings.to_exchange_string().is_err());
+        settings.resources.remove("ore-0");
+        let text = settings.to_exchange_string().unwrap();
+        assert_eq!(MapGenSettings::from_exchange_string(&text), Ok(settings));
+
+        //odd sizes cover exactly the configured number of tiles
+        let settings = MapGenSettings { width: 5, height: 1, ..Default::default() };
+        let text = settings.to_exchange_string().unwrap();
+        let limits = MapGenSettings::from_exchange_string(&text).unwrap().limits().unwrap();
+        assert_eq!(limits, TileArea::new(TileCoord::new(-2, 0), TileCoord::new(2, 0)));
+        assert_eq!((limits.width(), limits.height()), (5, 1));
+        let limits = MapGenSettings { width: u32::MAX, height: 7, ..Default::default() }.limits().unwrap();
+        assert_eq!((limits.min.x, limits.max.x), (-i32::MAX, i32::MAX));
+        assert_eq!((limits.min.y, limits.max.y), (-3, 3));
+    }
+
+    #[test]
+    fn stamps_round_trip() {
+        let tileman = crate::world::test_tiles();
+        let mut settings = MapGenSettings { seed: 3, ..Default::default() };
+        settings.stamps.push(StampSettings { tile: "sand".into(), min: [-10, -10], max: [9, 9] });
+        settings.stamps.push(StampSettings { tile: "dirt".into(), min: [0, i32::MIN], max: [0, i32::MAX] });
+        let text = settings.to_exchange_string().unwrap();
+        assert_eq!(MapGenSettings::from_exchange_string(&text), Ok(settings.clone()));
+        assert_eq!(settings.tile_stamps(&tileman).unwrap().len(), 2);
+
+        settings.stamps[1].tile = "lava".into();
+        let error = settings.tile_stamps(&tileman).unwrap_err();
+        assert!(error.contains("lava"), "{error}");
+        settings.stamps[1].tile = String::new();
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn exchange_string_without_extra_data() {
+        let settings = MapGenSettings { seed: 5, ..Default::default() };
+        let text = settings.to_exchange_string().unwrap();
+        let body = text.strip_prefix(">>>").unwrap().strip_suffix("<<<").unwrap();
+        let mut bytes = base64_decode(body).unwrap();
+        bytes.truncate(bytes.len() - 4);
+        assert_eq!(MapGenSettings::from_exchange_string(&encode_exchange_bytes(bytes.clone())), Ok(settings));
+
+        bytes.push(0);
+        assert!(MapGenSettings::from_exchange_string(&encode_exchange_bytes(bytes)).is_err());
+    }
+}
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+
+use super::{area::{ChunkArea, TileArea}, entity::EID, ChunkCoord};
+
+///buckets entities by the chunks their area overlaps, so position queries only have to look
+///at nearby entities. independent of which chunks are loaded
+#[derive(Debug, Default)]
+pub struct SpatialIndex {
+    cells: HashMap<ChunkCoord, Vec<EID>>,
+}
+
+impl SpatialIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, id: EID, area: TileArea) {
+        for coord in area.chunks().iter() {
+            self.cells.entry(coord).or_default().push(id);
+        }
+    }
+
+    ///area has to be the same area the entity was inserted with
+    pub fn remove(&mut self, id: EID, area: TileArea) {
+        for coord in area.chunks().iter() {
+            if let Some(cell) = self.cells.get_mut(&coord) {
+                cell.retain(|e| *e != id);
+                if cell.is_empty() {
+                    self.cells.remove(&coord);
+                }
+            }
+        }
+    }
+
+    ///entities overlapping the given chunk
+    pub fn in_chunk(&self, coord: ChunkCoord) -> &[EID] {
+        self.cells.get(&coord).map_or(&[], |c| c.as_slice())
+    }
+
+    ///entities that may overlap area, sorted by id and without duplicates
+    pub fn candidates(&self, area: ChunkArea) -> Vec<EID> {
+        let mut ids: Vec<EID> = area.iter().flat_map(|c| self.in_chunk(c).iter().copied()).collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+}
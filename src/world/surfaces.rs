@@ -0,0 +1,182 @@
+use super::{entity::{Entity, EID}, force::ForceManager, player::{Player, PlayerId}, position::MapPosition, worldgen::Generator, is_valid_surface_name, Surface, TileCoord};
+
+///owns every surface in the game, looked up by name
+pub struct SurfaceManager {
+    surfaces: Vec<Surface>,
+}
+
+impl SurfaceManager {
+    pub fn new() -> Self {
+        SurfaceManager { surfaces: vec![] }
+    }
+
+    ///creates a new empty surface. fails if a surface with that name already exists or the
+    ///name isn't valid, see is_valid_surface_name
+    pub fn create<T>(&mut self, name: &str, generator: T) -> Result<&mut Surface, ()>
+    where T: Generator + 'static {
+        if self.get(name).is_some() || !is_valid_surface_name(name) {
+            return Err(());
+        }
+        self.surfaces.push(Surface::new(name, generator));
+        Ok(self.surfaces.last_mut().unwrap())
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.surfaces.iter().position(|s| s.name() == name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Surface> {
+        self.surfaces.iter().find(|s| s.name() == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Surface> {
+        self.surfaces.iter_mut().find(|s| s.name() == name)
+    }
+
+    ///surfaces in the order they were created
+    #[allow(dead_code)]
+    pub fn iter(&self) -> impl Iterator<Item = &Surface> {
+        self.surfaces.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Surface> {
+        self.surfaces.iter_mut()
+    }
+
+    ///removes a surface along with its chunks, entities and players, and the charts forces have
+    ///of it. its chunk cache is deleted from disk once the returned surface is dropped
+    #[allow(dead_code)]
+    pub fn delete(&mut self, name: &str, forces: &mut ForceManager) -> Option<Surface> {
+        let idx = self.index(name)?;
+        forces.forget_surface(name);
+        Some(self.surfaces.remove(idx))
+    }
+
+    ///mutable references to two different surfaces at once
+    fn get_pair_mut(&mut self, a: &str, b: &str) -> Result<(&mut Surface, &mut Surface), ()> {
+        let ia = self.index(a).ok_or(())?;
+        let ib = self.index(b).ok_or(())?;
+        if ia == ib {
+            return Err(());
+        }
+
+        if ia < ib {
+            let (left, right) = self.surfaces.split_at_mut(ib);
+            Ok((&mut left[ia], &mut right[0]))
+        }
+        else {
+            let (left, right) = self.surfaces.split_at_mut(ia);
+            Ok((&mut right[0], &mut left[ib]))
+        }
+    }
+
+    ///moves an entity to position on another surface. the entity gets a new id there, which is returned
+    #[allow(dead_code)]
+    pub fn teleport_entity(&mut self, id: EID, from: &str, to: &str, position: TileCoord) -> Result<EID, ()> {
+        let (src, dst) = self.get_pair_mut(from, to)?;
+        let mut entity: Entity = src.remove_entity(id).ok_or(())?;
+        entity.position = position;
+        Ok(dst.place_entity(entity))
+    }
+
+    ///moves a player to position on another surface
+    #[allow(dead_code)]
+    pub fn teleport_player(&mut self, id: PlayerId, from: &str, to: &str, position: MapPosition) -> Result<(), ()> {
+        let (src, dst) = self.get_pair_mut(from, to)?;
+        let idx = src.players.iter().position(|p| p.id == id).ok_or(())?;
+        let mut player: Player = src.players.remove(idx);
+        player.position = position;
+        dst.players.push(player);
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{force::ForceId, worldgen::LabGen, ChunkCoord};
+
+    #[test]
+    fn create_rejects_taken_and_bad_names() {
+        let mut surfaces = SurfaceManager::new();
+        assert!(surfaces.create("main", LabGen).is_ok());
+        assert!(surfaces.create("lab", LabGen).is_ok());
+        assert!(surfaces.create("main", LabGen).is_err());
+        assert!(surfaces.create("../main", LabGen).is_err());
+        assert!(surfaces.create("", LabGen).is_err());
+
+        let names: Vec<&str> = surfaces.iter().map(|s| s.name()).collect();
+        assert_eq!(names, ["main", "lab"]);
+    }
+
+    #[test]
+    fn deleting_a_surface_takes_its_players_and_charts() {
+        let mut surfaces = SurfaceManager::new();
+        let mut forces = ForceManager::new();
+        surfaces.create("main", LabGen).unwrap();
+        surfaces.create("lab", LabGen).unwrap()
+            .players.push(Player::new(PlayerId(1), MapPosition::new(0, 0)));
+        let player = forces.get_mut(ForceId::PLAYER).unwrap();
+        player.chart("main", ChunkCoord::new(0, 0), 1);
+        player.chart("lab", ChunkCoord::new(0, 0), 1);
+
+        let lab = surfaces.delete("lab", &mut forces).unwrap();
+        assert_eq!(lab.players.len(), 1);
+        assert!(surfaces.get("lab").is_none());
+        assert!(surfaces.delete("lab", &mut forces).is_none());
+
+        let player = forces.get(ForceId::PLAYER).unwrap();
+        assert!(player.chart_of("lab").is_none());
+        assert!(player.is_charted("main", ChunkCoord::new(0, 0)));
+
+        //the player went with the surface
+        assert!(surfaces.teleport_player(PlayerId(1), "lab", "main", MapPosition::new(0, 0)).is_err());
+        assert!(surfaces.get("main").unwrap().players.is_empty());
+    }
+
+    #[test]
+    fn teleported_entities_move_between_indexes() {
+        let mut surfaces = SurfaceManager::new();
+        surfaces.create("main", LabGen).unwrap();
+        surfaces.create("lab", LabGen).unwrap();
+        let from = TileCoord::new(5, 5);
+        let id = surfaces.get_mut("main").unwrap().place_entity(Entity::new("chest", from));
+
+        //nothing moves if the target doesn't exist
+        assert!(surfaces.teleport_entity(id, "main", "nowhere", TileCoord::new(0, 0)).is_err());
+        assert!(surfaces.teleport_entity(id, "main", "main", TileCoord::new(0, 0)).is_err());
+        assert_eq!(surfaces.get("main").unwrap().entities_at(from).len(), 1);
+
+        let to = TileCoord::new(-40, 70);
+        let new_id = surfaces.teleport_entity(id, "main", "lab", to).unwrap();
+        let main = surfaces.get("main").unwrap();
+        assert!(main.get_entity(id).is_none());
+        assert!(main.entities_at(from).is_empty());
+        let lab = surfaces.get("lab").unwrap();
+        let moved = lab.entities_at(to);
+        assert_eq!(moved.len(), 1);
+        assert_eq!((moved[0].id(), moved[0].name.as_str()), (new_id, "chest"));
+        assert!(lab.entities_at(from).is_empty());
+
+        //an entity that isn't there can't be teleported
+        assert!(surfaces.teleport_entity(id, "main", "lab", to).is_err());
+    }
+
+    #[test]
+    fn teleporting_players() {
+        let mut surfaces = SurfaceManager::new();
+        surfaces.create("main", LabGen).unwrap()
+            .players.push(Player::new(PlayerId(7), MapPosition::new(0, 0)));
+        surfaces.create("lab", LabGen).unwrap();
+
+        assert!(surfaces.teleport_player(PlayerId(7), "main", "nowhere", MapPosition::new(1, 1)).is_err());
+        assert!(surfaces.teleport_player(PlayerId(8), "main", "lab", MapPosition::new(1, 1)).is_err());
+        assert_eq!(surfaces.get("main").unwrap().players.len(), 1);
+
+        surfaces.teleport_player(PlayerId(7), "main", "lab", MapPosition::new(3, 4)).unwrap();
+        assert!(surfaces.get("main").unwrap().players.is_empty());
+        let lab = surfaces.get("lab").unwrap();
+        assert_eq!((lab.players[0].id, lab.players[0].position), (PlayerId(7), MapPosition::new(3, 4)));
+    }
+}
@@ -0,0 +1,339 @@
+use super::{
+    chunk::{self, GenStatus, CHUNK_SIZE}, direction::Direction, entity::Entity, noise::{self, Fbm},
+    settings::MapGenSettings, tile::{TileId, TileManager}, vegetation::{DecorativeGen, TreeGen}, worldgen::{self, Generator, NoiseGen, StageContext, StageGenerator},
+    ChunkCoord, TileCoord,
+};
+
+///height of the land, shared by the tile generator and the cliff stage so both agree on it.
+///the land around spawn is raised so it is never under water or shore
+#[derive(Debug, Clone)]
+pub struct Elevation {
+    land: Fbm,
+    rivers: Fbm,
+    pub spawn: TileCoord,
+    ///radius in tiles around spawn that is kept dry. the land falls back to its own height
+    ///over the next DRY_FALLOFF tiles
+    pub dry_radius: f64,
+    ///below this height there is water
+    pub water_level: f64,
+    ///half the width of a river, in noise units
+    pub river_width: f64,
+}
+
+impl Elevation {
+    #[allow(dead_code)]
+    pub fn new(seed: u64) -> Self {
+        Self::with_water(seed, 1.0)
+    }
+
+    ///water scales how much of the map is covered by lakes and rivers. 0 leaves them out
+    pub fn with_water(seed: u64, water: f64) -> Self {
+        let water = water.max(0.0);
+        Self {
+            land: worldgen::elevation_field(seed),
+            rivers: Fbm::new(noise::sub_seed(seed, 4), 2, 1.0 / 400.0),
+            spawn: TileCoord::new(0, 0),
+            dry_radius: 48.0,
+            water_level: if water > 0.0 { -noise::tail_threshold(LAKE_SHARE * water) } else { f64::NEG_INFINITY },
+            river_width: RIVER_WIDTH * water,
+        }
+    }
+
+    fn is_water(&self, height: f64, river: f64) -> bool {
+        height < self.water_level || river < self.river_width
+    }
+
+    fn is_shore(&self, height: f64, river: f64) -> bool {
+        height < self.water_level + SHORE_BAND || river < self.river_width + RIVER_BANK
+    }
+
+    ///1 within dry_radius of spawn, falling to 0 over DRY_FALLOFF tiles beyond it
+    fn dryness(&self, x: f64, y: f64) -> f64 {
+        let d = ((x - self.spawn.x as f64).powi(2) + (y - self.spawn.y as f64).powi(2)).sqrt();
+        (1.0 - (d - self.dry_radius) / DRY_FALLOFF).clamp(0.0, 1.0)
+    }
+
+    ///height of the land at the center of a tile
+    pub fn height(&self, tile: TileCoord) -> f64 {
+        let (x, y) = (tile.x as f64 + 0.5, tile.y as f64 + 0.5);
+        //the land noise never goes below -1, so fully dry land stays well above the shore
+        self.land.sample(x, y) + self.dryness(x, y) * 1.5
+    }
+
+    ///distance to the middle of the nearest river, in noise units. rivers are where this is small
+    pub fn river(&self, tile: TileCoord) -> f64 {
+        let (x, y) = (tile.x as f64 + 0.5, tile.y as f64 + 0.5);
+        self.rivers.sample(x, y).abs() + self.dryness(x, y)
+    }
+
+    pub fn is_water_at(&self, tile: TileCoord) -> bool {
+        self.is_water(self.height(tile), self.river(tile))
+    }
+
+    ///whether tile is land that is close to the water level or next to water, diagonals included.
+    ///this way every water tile is surrounded by water or shore
+    pub fn is_shore_at(&self, tile: TileCoord) -> bool {
+        let (height, river) = (self.height(tile), self.river(tile));
+        !self.is_water(height, river)
+            && (self.is_shore(height, river) || tile.neighbors8().iter().any(|n| self.is_water_at(*n)))
+    }
+}
+
+///tiles beyond Elevation::dry_radius over which the raised land around spawn slopes down
+const DRY_FALLOFF: f64 = 32.0;
+///share of the map covered by lakes with the default settings
+const LAKE_SHARE: f64 = 0.07;
+///how far below the water level the water gets deep
+const DEEP_DEPTH: f64 = 0.12;
+///how far above the water level the shore reaches
+const SHORE_BAND: f64 = 0.04;
+///half the width of a river with the default settings
+const RIVER_WIDTH: f64 = 0.01;
+///rivers get banks this much wider than the river itself
+const RIVER_BANK: f64 = 0.004;
+
+///land tiles from a NoiseGen, with lakes, rivers and shores cut into them from the elevation.
+///needs the water, deepwater and shore tiles
+pub struct TerrainGen {
+    land: NoiseGen,
+    ///multiplier on how much of the map is water, see Elevation::with_water
+    pub water: f64,
+    tiles: WaterTiles,
+}
+
+///the tiles TerrainGen cuts into the land
+#[derive(Debug, Clone, Copy)]
+struct WaterTiles {
+    water: TileId,
+    deep: TileId,
+    shore: TileId,
+}
+
+impl TerrainGen {
+    ///fails if the tiles of the default biomes or the water tiles aren't registered
+    #[allow(dead_code)]
+    pub fn new(tileman: &TileManager) -> Result<Self, String> {
+        Self::with_water(1.0, tileman)
+    }
+
+    pub fn from_settings(settings: &MapGenSettings, tileman: &TileManager) -> Result<Self, String> {
+        Self::with_water(settings.water, tileman)
+    }
+
+    fn with_water(water: f64, tileman: &TileManager) -> Result<Self, String> {
+        let id = |name| tileman.get_id(name).map_err(|_| format!("tile \"{name}\" isn't registered"));
+        let tiles = WaterTiles { water: id("water")?, deep: id("deepwater")?, shore: id("shore")? };
+        Ok(Self { land: NoiseGen::new(tileman)?, water, tiles })
+    }
+
+    pub fn elevation(&self, seed: u64) -> Elevation {
+        Elevation::with_water(seed, self.water)
+    }
+
+    ///the cliff stage matching this terrain
+    pub fn cliffs(&self, seed: u64) -> CliffGen {
+        CliffGen::new(seed, self.elevation(seed))
+    }
+
+    ///the tree and rock stage matching this terrain. seed must be the surface's seed: the stage
+    ///works out the biomes again from the land and seed, and they have to match the tiles
+    pub fn trees(&self, seed: u64) -> TreeGen {
+        TreeGen::new(self.land.clone(), seed)
+    }
+
+    ///the decorative stage matching this terrain, with the same seed as trees.
+    ///fails if a decorative of the biomes isn't registered
+    pub fn decoratives(&self, seed: u64, tileman: &TileManager) -> Result<DecorativeGen, String> {
+        DecorativeGen::new(self.land.clone(), seed, tileman)
+    }
+}
+
+impl Generator for TerrainGen {
+    fn gen_chunk (&self, position: ChunkCoord, seed: u64, tileman: &TileManager)-> chunk::Chunk {
+        let mut chunk = self.land.gen_chunk(position, seed, tileman);
+        let elevation = self.elevation(seed);
+        let origin: TileCoord = position.into();
+
+        //samples the ring of tiles around the chunk as well, since water there makes shore here.
+        //same rules as Elevation::is_shore_at without sampling every tile nine times
+        const SIDE: usize = CHUNK_SIZE + 2;
+        let mut samples = Vec::with_capacity(SIDE * SIDE);
+        for x in 0..SIDE {
+            for y in 0..SIDE {
+                let tile = origin.offset(x as i32 - 1, y as i32 - 1);
+                samples.push((elevation.height(tile), elevation.river(tile)));
+            }
+        }
+        let water = |x: usize, y: usize| {
+            let (height, river) = samples[x*SIDE+y];
+            elevation.is_water(height, river)
+        };
+
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                let (height, river) = samples[(x+1)*SIDE+y+1];
+                let id = if height < elevation.water_level - DEEP_DEPTH {
+                    self.tiles.deep
+                } else if elevation.is_water(height, river) {
+                    self.tiles.water
+                } else if elevation.is_shore(height, river) || (0..3).any(|dx| (0..3).any(|dy| water(x+dx, y+dy))) {
+                    self.tiles.shore
+                } else {
+                    continue;
+                };
+                chunk.tiles[x*CHUNK_SIZE+y] = id;
+            }
+        }
+        chunk
+    }
+}
+
+
+///height difference between two rows of cliffs
+const CLIFF_INTERVAL: f64 = 0.15;
+
+///places cliff entities where the land crosses a multiple of CLIFF_INTERVAL.
+///cliffs are broken up by gaps so every plateau can be reached. runs in the Entities stage
+pub struct CliffGen {
+    elevation: Elevation,
+    gaps: Fbm,
+}
+
+impl CliffGen {
+    pub fn new(seed: u64, elevation: Elevation) -> Self {
+        Self { elevation, gaps: Fbm::new(noise::sub_seed(seed, 5), 2, 1.0 / 24.0) }
+    }
+
+    fn level(&self, tile: TileCoord) -> i32 {
+        //cliffs follow the land, not the water level, so they don't move with the water setting
+        (self.elevation.height(tile) / CLIFF_INTERVAL).floor() as i32
+    }
+
+    ///the downhill direction of a cliff at tile, if there is one
+    fn cliff_at(&self, tile: TileCoord) -> Option<Direction> {
+        if self.elevation.dryness(tile.x as f64, tile.y as f64) > 0.0 {
+            return None;
+        }
+        if self.gaps.sample(tile.x as f64, tile.y as f64) < -0.15 {
+            return None;
+        }
+        if self.elevation.is_water_at(tile) || self.elevation.is_shore_at(tile) {
+            return None;
+        }
+
+        let level = self.level(tile);
+        [Direction::East, Direction::South].into_iter()
+            .find(|d| self.level(tile.neighbor(*d)) != level)
+            .map(|d| if self.level(tile.neighbor(d)) < level { d } else { d.opposite() })
+    }
+}
+
+impl StageGenerator for CliffGen {
+    fn stage(&self) -> GenStatus {
+        GenStatus::Entities
+    }
+
+    fn generate(&mut self, chunk: &mut chunk::Chunk, ctx: &mut StageContext) {
+        let origin: TileCoord = chunk.position.into();
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 0..CHUNK_SIZE as i32 {
+                let tile = origin.offset(x, y);
+                let Some(direction) = self.cliff_at(tile) else { continue; };
+                let mut cliff = Entity::new("cliff", tile);
+                cliff.direction = direction;
+                cliff.solid = true;
+                ctx.entities.push(cliff);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{area::{ChunkArea, TileArea}, entity::EID, preview, test_tiles, Surface};
+
+    const SEEDS: [u64; 4] = [1, 42, 777, 20240611];
+
+    ///a finished map of the chunks within radius of the origin, with cliffs
+    fn terrain_surface(seed: u64, radius: i32, tileman: &TileManager) -> Surface {
+        let terrain = TerrainGen::new(tileman).unwrap();
+        let cliffs = terrain.cliffs(seed);
+        let mut surface = Surface::new("terrain-test", terrain);
+        surface.seed = seed;
+        let area = ChunkArea::around(ChunkCoord::new(0, 0), radius);
+        surface.limits = Some(area.tiles());
+        surface.add_stage(cliffs);
+        preview::generate(&mut surface, area, tileman);
+        surface
+    }
+
+    #[test]
+    fn spawn_is_dry_land() {
+        let tileman = test_tiles();
+        for seed in SEEDS {
+            let surface = terrain_surface(seed, 2, &tileman);
+            let elevation = Elevation::new(seed);
+            let radius = elevation.dry_radius as i32;
+            let shore = tileman.get_id("shore").unwrap();
+            for tile in TileArea::around(elevation.spawn, radius).iter() {
+                if (tile.x - elevation.spawn.x).pow(2) + (tile.y - elevation.spawn.y).pow(2) > radius * radius {
+                    continue;
+                }
+                assert!(surface.is_walkable(tile, &tileman) && surface.is_buildable(tile, &tileman), "{tile:?} with seed {seed}");
+                assert_ne!(surface.get_tile(tile), Some(shore), "{tile:?} with seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn water_is_bordered_by_shore() {
+        let tileman = test_tiles();
+        let wet = ["water", "deepwater", "shore"].map(|name| tileman.get_id(name).unwrap());
+        let mut water_tiles = 0;
+        for seed in SEEDS {
+            let surface = terrain_surface(seed, 4, &tileman);
+            for tile in surface.limits.unwrap().iter() {
+                if !surface.is_water(tile, &tileman) {
+                    continue;
+                }
+                water_tiles += 1;
+                assert!(!surface.is_walkable(tile, &tileman) && !surface.is_buildable(tile, &tileman));
+                for neighbor in tile.neighbors8() {
+                    let Some(id) = surface.get_tile(neighbor) else { continue; };
+                    assert!(wet.contains(&id), "water at {tile:?} touches land at {neighbor:?} with seed {seed}");
+                }
+            }
+        }
+        assert!(water_tiles > 1000, "only {water_tiles} water tiles");
+    }
+
+    #[test]
+    fn cliffs_block_walking_and_building() {
+        let tileman = test_tiles();
+        let mut checked = 0;
+        for seed in SEEDS {
+            let mut surface = terrain_surface(seed, 4, &tileman);
+            let elevation = Elevation::new(seed);
+            let cliffs: Vec<(EID, TileCoord)> = surface.entities()
+                .filter(|e| e.name == "cliff")
+                .map(|e| (e.id(), e.position))
+                .collect();
+            assert!(!cliffs.is_empty(), "no cliffs with seed {seed}");
+
+            for (id, tile) in cliffs.into_iter().step_by(7) {
+                let distance = ((tile.x - elevation.spawn.x) as f64).hypot((tile.y - elevation.spawn.y) as f64);
+                assert!(distance > elevation.dry_radius, "cliff at {tile:?} near spawn with seed {seed}");
+                //the tile itself is fine, the cliff is what is in the way
+                assert!(surface.tile_properties(tile, &tileman).is_some_and(|p| p.walkable && p.buildable));
+                assert!(!surface.is_walkable(tile, &tileman) && !surface.is_buildable(tile, &tileman), "{tile:?}");
+
+                surface.remove_entity(id);
+                assert!(surface.is_walkable(tile, &tileman) && surface.is_buildable(tile, &tileman), "{tile:?}");
+                checked += 1;
+            }
+        }
+        assert!(checked > 100);
+    }
+}
@@ -1,21 +1,82 @@
 use notan::app::{Graphics, Texture};
 
+///index of a tile type in the TileManager. this is all a chunk stores per tile
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct TileId(u16);
+
+impl TileId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+///index of a decorative type in the TileManager
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct DecorativeId(u16);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct MapColor { pub r: u8, pub g: u8, pub b: u8 }
+
+impl MapColor {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self { Self {r, g, b} }
+}
+
+///gameplay properties of a tile type, declared when the tile is registered
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TileProperties {
+    pub walkable: bool,
+    ///whether entities can be placed on this tile
+    pub buildable: bool,
+    pub water: bool,
+    ///multiplier applied to walking speed on this tile
+    pub speed_modifier: f32,
+    ///pollution absorbed per second
+    pub pollution_absorption: f32,
+    ///color used by the minimap and map views
+    pub map_color: MapColor,
+}
+
+impl Default for TileProperties {
+    fn default() -> Self {
+        Self {
+            walkable: true,
+            buildable: true,
+            water: false,
+            speed_modifier: 1.0,
+            pollution_absorption: 0.0,
+            map_color: MapColor::new(128, 128, 128),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Tile {
     pub name: String,
-    pub texture: Texture,
+    ///None for tiles registered without graphics (tests, headless tools)
+    pub texture: Option<Texture>,
+    pub properties: TileProperties,
 }
 
 
 pub struct TileManager {
-    tiles: Vec<Tile>
+    tiles: Vec<Tile>,
+    ///names of the purely visual decoratives drawn on top of tiles
+    decoratives: Vec<String>,
 }
 
 impl TileManager {
     pub fn new() -> TileManager {
-        Self { tiles: vec![] }
+        Self { tiles: vec![], decoratives: vec![] }
+    }
+    #[allow(dead_code)]
+    pub fn register_tile(&mut self, tile_name: &str, texture: &str, properties: TileProperties, gfx: &mut Graphics) -> TileId {
+        let id = self.add_tile(tile_name, properties);
+        self.load_texture(id, texture, gfx);
+        id
     }
-    pub fn register_tile(&mut self, tile_name: &str, texture: &str, gfx: &mut Graphics) {
+
+    ///gives a tile registered with add_tile its texture
+    pub fn load_texture(&mut self, id: TileId, texture: &str, gfx: &mut Graphics) {
 
         let bytes = &get_file_as_byte_vec(&texture.to_owned());        
 
@@ -26,19 +87,74 @@ impl TileManager {
                         .build()
                         .unwrap();
 
-        let tile = Tile {name: tile_name.to_owned(), texture};
-        self.tiles.push(tile);
+        if let Some(tile) = self.tiles.get_mut(id.index()) {
+            tile.texture = Some(texture);
+        }
+    }
+
+    ///registers a tile type without a texture. registering a name twice updates the
+    ///properties of the existing tile and returns its id
+    pub fn add_tile(&mut self, tile_name: &str, properties: TileProperties) -> TileId {
+        if let Ok(id) = self.get_id(tile_name) {
+            self.tiles[id.index()].properties = properties;
+            return id;
+        }
 
+        assert!(self.tiles.len() < u16::MAX as usize, "too many tile types");
+        self.tiles.push(Tile {name: tile_name.to_owned(), texture: None, properties});
+        TileId((self.tiles.len() - 1) as u16)
     }
 
-    pub fn get_tile(&self, tile_name: &str) -> Result<Tile, ()> {
-        for t in &self.tiles {
+    pub fn get_id(&self, tile_name: &str) -> Result<TileId, ()> {
+        for (i, t) in self.tiles.iter().enumerate() {
             if t.name.eq_ignore_ascii_case(tile_name) {
-                return Ok(t.clone());
+                return Ok(TileId(i as u16));
             }
         }
         return Err(());
     }
+
+    pub fn get_tile(&self, id: TileId) -> Option<&Tile> {
+        self.tiles.get(id.index())
+    }
+
+    pub fn name(&self, id: TileId) -> Option<&str> {
+        self.get_tile(id).map(|t| t.name.as_str())
+    }
+
+    pub fn texture(&self, id: TileId) -> Option<&Texture> {
+        self.get_tile(id).and_then(|t| t.texture.as_ref())
+    }
+
+    pub fn properties(&self, id: TileId) -> Option<&TileProperties> {
+        self.get_tile(id).map(|t| &t.properties)
+    }
+
+    ///names of all tile types, in id order
+    pub fn tile_names(&self) -> impl Iterator<Item = &str> {
+        self.tiles.iter().map(|t| t.name.as_str())
+    }
+
+    ///registers a decorative type. registering a name twice returns the existing id
+    pub fn add_decorative(&mut self, name: &str) -> DecorativeId {
+        if let Ok(id) = self.decorative_id(name) {
+            return id;
+        }
+        assert!(self.decoratives.len() < u16::MAX as usize, "too many decorative types");
+        self.decoratives.push(name.to_owned());
+        DecorativeId((self.decoratives.len() - 1) as u16)
+    }
+
+    pub fn decorative_id(&self, name: &str) -> Result<DecorativeId, ()> {
+        self.decoratives.iter()
+            .position(|d| d.eq_ignore_ascii_case(name))
+            .map(|i| DecorativeId(i as u16))
+            .ok_or(())
+    }
+
+    pub fn decorative_name(&self, id: DecorativeId) -> Option<&str> {
+        self.decoratives.get(id.0 as usize).map(|d| d.as_str())
+    }
 }
 
 
@@ -53,4 +169,4 @@ fn get_file_as_byte_vec(filename: &String) -> Vec<u8> {
     std::io::Read::read(&mut f, &mut buffer).expect("buffer overflow");
 
     buffer
-}
\ No newline at end of file
+}
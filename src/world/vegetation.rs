@@ -0,0 +1,278 @@
+use super::{
+    chunk::{Chunk, GenStatus, CHUNK_SIZE}, entity::{Entity, Minable}, noise::{self, Fbm},
+    tile::{DecorativeId, TileManager}, worldgen::{Climate, NoiseGen, StageContext, StageGenerator}, TileCoord,
+};
+
+///whether anything may grow on the tile at coord (relative to the chunk)
+fn is_land(chunk: &Chunk, coord: TileCoord, tileman: &TileManager) -> bool {
+    chunk.get_tile(coord).ok()
+        .and_then(|t| tileman.properties(t))
+        .is_some_and(|p| p.buildable && !p.water)
+}
+
+///places trees and rocks. how many depends on the biome, and trees bunch up into forests
+///where the forest noise is high. runs in the Entities stage
+pub struct TreeGen {
+    land: NoiseGen,
+    climate: Climate,
+    seed: u64,
+    forest: Fbm,
+}
+
+impl TreeGen {
+    pub fn new(land: NoiseGen, seed: u64) -> Self {
+        let forest = Fbm::new(noise::sub_seed(seed, 6), 3, 1.0 / 96.0);
+        Self { land, climate: Climate::new(seed), seed, forest }
+    }
+
+    fn tree(tile: TileCoord) -> Entity {
+        let mut tree = Entity::new("tree", tile);
+        tree.solid = true;
+        tree.minable = Some(Minable::new("wood", 4));
+        tree
+    }
+
+    fn rock(tile: TileCoord) -> Entity {
+        let mut rock = Entity::new("rock", tile);
+        rock.solid = true;
+        rock.minable = Some(Minable::new("stone", 20));
+        rock
+    }
+}
+
+impl StageGenerator for TreeGen {
+    fn stage(&self) -> GenStatus {
+        GenStatus::Entities
+    }
+
+    fn generate(&mut self, chunk: &mut Chunk, ctx: &mut StageContext) {
+        let seed = self.seed;
+        let origin: TileCoord = chunk.position.into();
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 0..CHUNK_SIZE as i32 {
+                if !is_land(chunk, TileCoord::new(x, y), ctx.tileman) {
+                    continue;
+                }
+                let tile = origin.offset(x, y);
+                let Some(biome) = self.land.biome_at(&self.climate, tile) else { continue; };
+
+                let forest = ((self.forest.sample(tile.x as f64, tile.y as f64) + 0.1) / 0.4).clamp(0.0, 1.0);
+                if noise::random(noise::sub_seed(seed, 7), tile.x, tile.y) < biome.trees * forest {
+                    ctx.entities.push(Self::tree(tile));
+                }
+                else if noise::random(noise::sub_seed(seed, 8), tile.x, tile.y) < biome.rocks {
+                    ctx.entities.push(Self::rock(tile));
+                }
+            }
+        }
+    }
+}
+
+
+///scatters the decoratives of each biome over its tiles. runs in the Decoratives stage
+pub struct DecorativeGen {
+    land: NoiseGen,
+    climate: Climate,
+    seed: u64,
+    ///the decoratives of each biome with their chance per tile, by biome index
+    decoratives: Vec<Vec<(DecorativeId, f64)>>,
+}
+
+impl DecorativeGen {
+    ///fails if a decorative of one of the biomes isn't registered
+    pub fn new(land: NoiseGen, seed: u64, tileman: &TileManager) -> Result<Self, String> {
+        let mut decoratives = vec![];
+        for biome in &land.biomes().biomes {
+            let mut chances = vec![];
+            for (name, chance) in &biome.decoratives {
+                let id = tileman.decorative_id(name)
+                    .map_err(|_| format!("biome {} uses decorative \"{name}\", which isn't registered", biome.name))?;
+                chances.push((id, *chance));
+            }
+            decoratives.push(chances);
+        }
+        Ok(Self { land, climate: Climate::new(seed), seed, decoratives })
+    }
+}
+
+impl StageGenerator for DecorativeGen {
+    fn stage(&self) -> GenStatus {
+        GenStatus::Decoratives
+    }
+
+    fn generate(&mut self, chunk: &mut Chunk, ctx: &mut StageContext) {
+        let seed = noise::sub_seed(self.seed, 9);
+        let origin: TileCoord = chunk.position.into();
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 0..CHUNK_SIZE as i32 {
+                let coord = TileCoord::new(x, y);
+                if !is_land(chunk, coord, ctx.tileman) {
+                    continue;
+                }
+                let tile = origin.offset(x, y);
+                let Some(biome) = self.land.biome_index_at(&self.climate, tile) else { continue; };
+
+                //one roll per tile, so a tile gets at most one decorative
+                let mut roll = noise::random(seed, tile.x, tile.y);
+                for (id, chance) in &self.decoratives[biome] {
+                    if roll < *chance {
+                        let _ = chunk.add_decorative(coord, *id);
+                        break;
+                    }
+                    roll -= chance;
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+    use crate::world::{
+        area::{ChunkArea, TileArea}, biome::{Biome, BiomeTable}, test_tiles, worldgen::{Generator, LabGen}, ChunkCoord, Surface,
+    };
+
+    ///runs stage over the chunks within two chunks of the origin, returning them with the
+    ///entities it placed
+    fn run_stage<S: StageGenerator>(land: &NoiseGen, stage: &mut S, seed: u64, tileman: &TileManager) -> (Vec<Chunk>, Vec<Entity>) {
+        let neighbors = HashMap::new();
+        let mut ctx = StageContext { chunks: &neighbors, tileman, entities: vec![] };
+        let chunks = ChunkArea::around(ChunkCoord::new(0, 0), 2).iter()
+            .map(|coord| {
+                let mut chunk = land.gen_chunk(coord, seed, tileman);
+                stage.generate(&mut chunk, &mut ctx);
+                chunk
+            })
+            .collect();
+        (chunks, ctx.entities)
+    }
+
+    ///every decorative of the chunks with the tile it is on
+    fn decoratives(chunks: &[Chunk]) -> Vec<(TileCoord, DecorativeId)> {
+        chunks.iter()
+            .flat_map(|c| c.decoratives.iter().map(|d| {
+                let cell = d.cell as i32;
+                (TileCoord::from(c.position).offset(cell / CHUNK_SIZE as i32, cell % CHUNK_SIZE as i32), d.id)
+            }))
+            .collect()
+    }
+
+    #[test]
+    fn trees_and_rocks_are_minable() {
+        let tileman = test_tiles();
+        let mut table = BiomeTable::new();
+        table.add(Biome::new("woods", 0.0, 0.0, 0.0, &[("grass", 1)]).with_trees(0.5).with_rocks(0.2));
+        let land = NoiseGen::with_biomes(&table, &tileman).unwrap();
+        let (_, entities) = run_stage(&land, &mut TreeGen::new(land.clone(), 5), 5, &tileman);
+
+        let count = |name| entities.iter().filter(|e| e.name == name).count();
+        let (trees, rocks) = (count("tree"), count("rock"));
+        assert!(trees > 1000 && rocks > 1000, "{trees} trees and {rocks} rocks");
+        assert_eq!(trees + rocks, entities.len());
+        for entity in &entities {
+            let expected = if entity.name == "tree" { Minable::new("wood", 4) } else { Minable::new("stone", 20) };
+            assert_eq!(entity.minable.as_ref(), Some(&expected));
+            assert!(entity.solid && entity.resource_amount.is_none());
+        }
+        //at most one per tile
+        let tiles: HashSet<TileCoord> = entities.iter().map(|e| e.position).collect();
+        assert_eq!(tiles.len(), entities.len());
+
+        let mut surface = Surface::new("tree-test", LabGen);
+        let tree = surface.place_entity(entities.into_iter().find(|e| e.name == "tree").unwrap());
+        assert_eq!(surface.mine_entity(tree), Some(Minable::new("wood", 4)));
+        assert!(surface.get_entity(tree).is_none());
+        assert_eq!(surface.mine_entity(tree), None);
+    }
+
+    #[test]
+    fn decoratives_follow_their_biome() {
+        let tileman = test_tiles();
+        let land = NoiseGen::new(&tileman).unwrap();
+        let mut kinds = HashSet::new();
+        for seed in [1, 42, 777, 20240611] {
+            let climate = Climate::new(seed);
+            let (chunks, _) = run_stage(&land, &mut DecorativeGen::new(land.clone(), seed, &tileman).unwrap(), seed, &tileman);
+            let placed = decoratives(&chunks);
+            assert!(!placed.is_empty());
+
+            let tiles: HashSet<TileCoord> = placed.iter().map(|(tile, _)| *tile).collect();
+            assert_eq!(tiles.len(), placed.len(), "a tile got two decoratives with seed {seed}");
+            for (tile, id) in placed {
+                let name = tileman.decorative_name(id).unwrap();
+                let biome = land.biome_at(&climate, tile).unwrap();
+                assert!(biome.decoratives.iter().any(|(d, _)| d == name), "{name} in {} at {tile:?}", biome.name);
+                kinds.insert(name);
+            }
+        }
+        assert!(kinds.len() >= 3, "{kinds:?}");
+    }
+
+    #[test]
+    fn decoratives_come_at_their_chance() {
+        let tileman = test_tiles();
+        let mut table = BiomeTable::new();
+        table.add(Biome::new("meadow", 0.0, 0.0, 0.0, &[("grass", 1)]).with_decoratives(&[("flowers", 0.3), ("pebbles", 0.1)]));
+        let land = NoiseGen::with_biomes(&table, &tileman).unwrap();
+        let (chunks, _) = run_stage(&land, &mut DecorativeGen::new(land.clone(), 3, &tileman).unwrap(), 3, &tileman);
+
+        let placed = decoratives(&chunks);
+        let tiles = (chunks.len() * CHUNK_SIZE * CHUNK_SIZE) as f64;
+        let share = |name| placed.iter().filter(|(_, id)| *id == tileman.decorative_id(name).unwrap()).count() as f64 / tiles;
+        assert!((share("flowers") - 0.3).abs() < 0.02, "{}", share("flowers"));
+        assert!((share("pebbles") - 0.1).abs() < 0.02, "{}", share("pebbles"));
+        let ids: HashSet<DecorativeId> = placed.iter().map(|(_, id)| *id).collect();
+        assert_eq!(ids.len(), 2);
+
+        //decoratives are looked up when the stage is built
+        table.biomes[0].decoratives.push(("mushrooms".into(), 0.1));
+        let land = NoiseGen::with_biomes(&table, &tileman).unwrap();
+        let error = DecorativeGen::new(land, 3, &tileman).err().unwrap();
+        assert!(error.contains("meadow") && error.contains("mushrooms"), "{error}");
+    }
+
+    #[test]
+    fn building_clears_decoratives() {
+        let tileman = test_tiles();
+        let mut surface = Surface::new("decorative-test", LabGen);
+        for coord in ChunkArea::around(ChunkCoord::new(0, 0), 1).iter() {
+            surface.gen_chunk(coord, &tileman);
+        }
+        let pebbles = tileman.decorative_id("pebbles").unwrap();
+        let area = TileArea::new(TileCoord::new(29, 29), TileCoord::new(35, 35));
+        for tile in area.iter() {
+            let chunk = surface.get_chunk_mut(tile.into()).unwrap();
+            chunk.add_decorative(chunk.position.within_chunk(tile), pebbles).unwrap();
+        }
+        let has_decorative = |s: &Surface, tile: TileCoord| {
+            let chunk = s.get_chunk(tile.into()).unwrap();
+            !chunk.decoratives_at(chunk.position.within_chunk(tile)).is_empty()
+        };
+        surface.take_dirty_chunks();
+
+        //a 2x2 building on the corner of four chunks
+        let mut furnace = Entity::new("furnace", TileCoord::new(31, 31));
+        furnace.footprint = TileArea::new(TileCoord::new(0, 0), TileCoord::new(1, 1));
+        let footprint = furnace.area();
+        surface.place_entity(furnace);
+        for tile in area.iter() {
+            assert_eq!(has_decorative(&surface, tile), !footprint.contains(tile), "{tile:?}");
+        }
+        assert_eq!(surface.take_dirty_chunks().len(), 4);
+
+        //resources lie under the decoratives
+        let mut ore = Entity::new("iron-ore", TileCoord::new(34, 34));
+        ore.resource_amount = Some(100);
+        surface.place_entity(ore);
+        assert!(has_decorative(&surface, TileCoord::new(34, 34)));
+
+        let sand = tileman.get_id("sand").unwrap();
+        assert!(surface.place_tile(TileCoord::new(29, 29), sand));
+        assert!(!has_decorative(&surface, TileCoord::new(29, 29)));
+        assert!(has_decorative(&surface, TileCoord::new(29, 30)));
+    }
+}
@@ -1,26 +1,111 @@
-use crate::world::{chunk::{self, CHUNK_SIZE}, tile::TileManager};
+use std::{collections::HashMap, sync::Arc};
 
-use super::ChunkCoord;
+use crate::world::{chunk::{self, GenStatus, CHUNK_SIZE}, tile::{TileId, TileManager}};
 
+use super::{area::TileArea, biome::{Biome, BiomeTable, ResolvedBiomes}, entity::Entity, noise::{self, Fbm}, ChunkCoord, TileCoord};
 
 
-pub trait Generator {
-    fn gen_chunk(&mut self, coords: ChunkCoord, tileman: &TileManager)-> chunk::Chunk;
+
+///produces the tiles of a chunk. this is the first generation stage.
+///generators run on worker threads, so the result may only depend on the generator, the seed
+///of the map and the chunk's position
+pub trait Generator: Send + Sync {
+    fn gen_chunk(&self, coords: ChunkCoord, seed: u64, tileman: &TileManager)-> chunk::Chunk;
+}
+
+///a pass over a chunk in the tile stage, run after the base generator of a Pipeline. it sees
+///the chunk as the generator and the passes before it left it. like generators, passes run on
+///worker threads and may only depend on themselves, the seed and the chunk.
+///anything that places entities or reads neighboring chunks belongs in a StageGenerator
+pub trait Overlay: Send + Sync {
+    fn apply(&self, chunk: &mut chunk::Chunk, seed: u64, tileman: &TileManager);
+}
+
+///a base generator followed by overlay passes, in the order they were added.
+///cloning is cheap, the generator and passes are shared
+#[derive(Clone)]
+pub struct Pipeline {
+    base: Arc<dyn Generator>,
+    overlays: Vec<Arc<dyn Overlay>>,
+}
+
+impl Pipeline {
+    pub fn new<T>(base: T) -> Self
+    where T: Generator + 'static {
+        Self { base: Arc::new(base), overlays: vec![] }
+    }
+
+    #[allow(dead_code)]
+    pub fn with<T>(mut self, overlay: T) -> Self
+    where T: Overlay + 'static {
+        self.push(overlay);
+        self
+    }
+
+    pub fn push<T>(&mut self, overlay: T)
+    where T: Overlay + 'static {
+        self.overlays.push(Arc::new(overlay));
+    }
+
+    ///number of overlay passes
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.overlays.len()
+    }
+}
+
+impl Generator for Pipeline {
+    fn gen_chunk(&self, coords: ChunkCoord, seed: u64, tileman: &TileManager)-> chunk::Chunk {
+        let mut chunk = self.base.gen_chunk(coords, seed, tileman);
+        for overlay in &self.overlays {
+            overlay.apply(&mut chunk, seed, tileman);
+        }
+        chunk
+    }
+}
+
+///one of the generation stages after Tiles. when it runs, every neighbor of the chunk has
+///completed at least the previous stage, so it can safely read across chunk borders
+pub trait StageGenerator {
+    ///the stage this generator runs in. must not be GenStatus::Tiles
+    fn stage(&self) -> GenStatus;
+    fn generate(&mut self, chunk: &mut chunk::Chunk, ctx: &mut StageContext);
+}
+
+///what a stage generator gets to see besides the chunk it is working on
+#[allow(dead_code)]
+pub struct StageContext<'a> {
+    ///all loaded chunks except the one being generated
+    pub chunks: &'a HashMap<ChunkCoord, chunk::Chunk>,
+    pub tileman: &'a TileManager,
+    ///entities to add to the surface once the stage is done
+    pub entities: Vec<Entity>,
 }
 
+impl StageContext<'_> {
+    ///tile of a neighboring chunk
+    #[allow(dead_code)]
+    pub fn neighbor_tile(&self, coord: TileCoord) -> Option<TileId> {
+        let chunk = self.chunks.get(&coord.into())?;
+        chunk.get_tile(chunk.position.within_chunk(coord)).ok()
+    }
+}
+
+///checkerboard of the two grass tiles, handy for checking the renderer
 #[derive(Default, Debug, Clone, Copy)]
+#[allow(dead_code)]
 pub struct LabGen;
 
 impl Generator for LabGen {
-    fn gen_chunk (&mut self, position: ChunkCoord, tileman: &TileManager)-> chunk::Chunk {
-        let grass = tileman.get_tile("grass").unwrap();
-        let grass2 = tileman.get_tile("grass1").unwrap();
+    fn gen_chunk (&self, position: ChunkCoord, _seed: u64, tileman: &TileManager)-> chunk::Chunk {
+        let grass = tileman.get_id("grass").unwrap();
+        let grass2 = tileman.get_id("grass1").unwrap();
 
-        let mut tiles = [(); CHUNK_SIZE*CHUNK_SIZE].map(|_| grass.clone());
+        let mut tiles = [grass; CHUNK_SIZE*CHUNK_SIZE];
         for i in 0..CHUNK_SIZE {
             for j in 0..CHUNK_SIZE {
                 if i%2 != j%2 {
-                    tiles[i*CHUNK_SIZE+j] = grass2.clone();
+                    tiles[i*CHUNK_SIZE+j] = grass2;
                 }
             }
         }
@@ -33,14 +118,190 @@ impl Generator for LabGen {
 pub struct Origin;
 
 impl Generator for Origin {
-    fn gen_chunk (&mut self, position: ChunkCoord, tileman: &TileManager)-> chunk::Chunk {
-        let grass = tileman.get_tile("grass").unwrap();
-        let grass2 = tileman.get_tile("grass1").unwrap();
+    fn gen_chunk (&self, position: ChunkCoord, _seed: u64, tileman: &TileManager)-> chunk::Chunk {
+        let grass = tileman.get_id("grass").unwrap();
+        let grass2 = tileman.get_id("grass1").unwrap();
 
-        let mut tiles = [(); CHUNK_SIZE*CHUNK_SIZE].map(|_| grass.clone());
+        let mut tiles = [grass; CHUNK_SIZE*CHUNK_SIZE];
         if position.x == 0 && position.y == 0 {
-            tiles[0] = grass2.clone();
+            tiles[0] = grass2;
         }
         return chunk::Chunk::new(position, tiles);
     }
-}
\ No newline at end of file
+}
+
+
+///replaces every tile in area with one tile type, e.g. to lay down the floor of a structure or
+///to clear the spawn area
+#[derive(Debug, Clone)]
+pub struct TileStamp {
+    area: TileArea,
+    tile: TileId,
+}
+
+impl TileStamp {
+    ///fails if tile isn't registered
+    pub fn new(area: TileArea, tile: &str, tileman: &TileManager) -> Result<Self, String> {
+        let tile = tileman.get_id(tile).map_err(|_| format!("stamp tile \"{tile}\" isn't registered"))?;
+        Ok(Self { area, tile })
+    }
+}
+
+impl Overlay for TileStamp {
+    fn apply(&self, chunk: &mut chunk::Chunk, _seed: u64, _tileman: &TileManager) {
+        let Some(area) = self.area.intersection(&chunk.position.into()) else { return; };
+        for tile in area.iter() {
+            let _ = chunk.set_tile(chunk.position.within_chunk(tile), self.tile);
+        }
+    }
+}
+
+
+///the elevation noise of a map. biomes and water both follow it
+pub fn elevation_field(seed: u64) -> Fbm {
+    Fbm::new(noise::sub_seed(seed, 3), 4, 1.0 / 128.0)
+}
+
+///the temperature, moisture and elevation fields of a map
+#[derive(Debug, Clone)]
+pub struct Climate {
+    temperature: Fbm,
+    moisture: Fbm,
+    elevation: Fbm,
+}
+
+impl Climate {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            temperature: Fbm::new(noise::sub_seed(seed, 1), 3, 1.0 / 384.0),
+            moisture: Fbm::new(noise::sub_seed(seed, 2), 3, 1.0 / 256.0),
+            elevation: elevation_field(seed),
+        }
+    }
+
+    ///temperature, moisture and elevation at the center of tile
+    pub fn sample(&self, tile: TileCoord) -> (f64, f64, f64) {
+        let (x, y) = (tile.x as f64 + 0.5, tile.y as f64 + 0.5);
+        (self.temperature.sample(x, y), self.moisture.sample(x, y), self.elevation.sample(x, y))
+    }
+}
+
+///natural looking terrain from layered gradient noise. every tile is sampled at its world
+///position, so chunks line up seamlessly and come out the same no matter when they are generated.
+///independent temperature, moisture and elevation fields decide the biome of each tile
+#[derive(Debug, Clone)]
+pub struct NoiseGen {
+    biomes: ResolvedBiomes,
+}
+
+impl NoiseGen {
+    ///uses BiomeTable::default_table. fails if its tiles aren't registered
+    pub fn new(tileman: &TileManager) -> Result<Self, String> {
+        Self::with_biomes(&BiomeTable::default_table(), tileman)
+    }
+
+    pub fn with_biomes(biomes: &BiomeTable, tileman: &TileManager) -> Result<Self, String> {
+        Ok(Self { biomes: biomes.resolve(tileman)? })
+    }
+
+    pub fn biomes(&self) -> &BiomeTable {
+        self.biomes.table()
+    }
+
+    ///the biome at the center of tile
+    pub fn biome_at(&self, climate: &Climate, tile: TileCoord) -> Option<&Biome> {
+        let (t, m, e) = climate.sample(tile);
+        self.biomes().select(t, m, e)
+    }
+
+    ///index into biomes() of the biome at the center of tile
+    pub fn biome_index_at(&self, climate: &Climate, tile: TileCoord) -> Option<usize> {
+        let (t, m, e) = climate.sample(tile);
+        self.biomes().select_index(t, m, e)
+    }
+}
+
+impl Generator for NoiseGen {
+    fn gen_chunk (&self, position: ChunkCoord, seed: u64, _tileman: &TileManager)-> chunk::Chunk {
+        let biomes = &self.biomes;
+        let climate = Climate::new(seed);
+
+        let origin: TileCoord = position.into();
+        let mut tiles = [TileId::default(); CHUNK_SIZE*CHUNK_SIZE];
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                let tile = origin.offset(x as i32, y as i32);
+                let (t, m, e) = climate.sample(tile);
+                tiles[x*CHUNK_SIZE+y] = biomes.tile(seed, tile.x, tile.y, t, m, e).expect("resolved biome tables aren't empty");
+            }
+        }
+
+        return chunk::Chunk::new(position, tiles);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::test_tiles as tiles;
+
+    #[test]
+    fn noise_gen_out_of_order() {
+        let tileman = tiles();
+        let coords: Vec<ChunkCoord> = (-2..=2).flat_map(|x| (-2..=2).map(move |y| ChunkCoord::new(x, y))).collect();
+
+        let forward = NoiseGen::new(&tileman).unwrap();
+        let first: Vec<_> = coords.iter().map(|c| forward.gen_chunk(*c, 1234, &tileman).tiles).collect();
+
+        let backward = NoiseGen::new(&tileman).unwrap();
+        let mut second: Vec<_> = coords.iter().rev().map(|c| backward.gen_chunk(*c, 1234, &tileman).tiles).collect();
+        second.reverse();
+        assert!(first == second);
+
+        //regenerating with the same instance doesn't depend on what it made before
+        assert!(forward.gen_chunk(coords[3], 1234, &tileman).tiles == first[3]);
+    }
+
+    #[test]
+    fn noise_gen_uses_every_biome() {
+        let tileman = tiles();
+        let gen = NoiseGen::new(&tileman).unwrap();
+        let mut seen = std::collections::HashSet::new();
+        for x in -12..12 {
+            for y in -12..12 {
+                seen.extend(gen.gen_chunk(ChunkCoord::new(x, y), 99, &tileman).tiles);
+            }
+        }
+        for name in ["grass", "grass1", "sand", "dirt"] {
+            assert!(seen.contains(&tileman.get_id(name).unwrap()), "no {name}");
+        }
+    }
+
+    #[test]
+    fn pipeline_runs_overlays_in_order() {
+        let tileman = tiles();
+        let (sand, dirt) = (tileman.get_id("sand").unwrap(), tileman.get_id("dirt").unwrap());
+        let pipeline = Pipeline::new(LabGen)
+            .with(TileStamp::new(TileArea::new(TileCoord::new(-4, -4), TileCoord::new(3, 3)), "sand", &tileman).unwrap())
+            .with(TileStamp::new(TileArea::new(TileCoord::new(0, 0), TileCoord::new(40, 0)), "dirt", &tileman).unwrap());
+
+        let chunk = pipeline.gen_chunk(ChunkCoord::new(0, 0), 5, &tileman);
+        assert_eq!(chunk.get_tile(TileCoord::new(0, 0)), Ok(dirt));
+        assert_eq!(chunk.get_tile(TileCoord::new(31, 0)), Ok(dirt));
+        assert_eq!(chunk.get_tile(TileCoord::new(3, 3)), Ok(sand));
+        assert_ne!(chunk.get_tile(TileCoord::new(4, 4)), Ok(sand));
+
+        let west = pipeline.gen_chunk(ChunkCoord::new(-1, -1), 5, &tileman);
+        assert_eq!(west.get_tile(TileCoord::new(31, 31)), Ok(sand));
+        assert_ne!(west.get_tile(TileCoord::new(27, 27)), Ok(sand));
+    }
+
+    #[test]
+    fn noise_gen_seeds_differ() {
+        let tileman = tiles();
+        let a = NoiseGen::new(&tileman).unwrap().gen_chunk(ChunkCoord::new(0, 0), 1, &tileman);
+        let b = NoiseGen::new(&tileman).unwrap().gen_chunk(ChunkCoord::new(0, 0), 2, &tileman);
+        assert!(a.tiles != b.tiles);
+    }
+}